@@ -1,16 +1,28 @@
-use crate::cache::SizeCache;
-use crate::changes::{get_fingerprint_path, DirectoryFingerprint, SizeChange};
-use crate::delete;
+use crate::cache::{get_cache_path, SizeCache};
+use crate::changes::{ChangeKind, DirectoryFingerprint, DirectoryHistory, SizeChange};
+use crate::delete::{self, DeleteMethod, DeleteTotals};
 use crate::logger;
 use crate::modal::Modal;
 use crate::platform::{self, DiskSpace};
 use crate::scan;
-use crate::scan::DirEntry;
+use crate::scan::{DirEntry, ScanError, ScanOptions, SizeMode, SortMode};
+use crate::tree::{DirTree, Node};
+use crate::undo;
+use crate::watch::{self, Watcher};
 use chrono::Local;
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread::{self, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Minimum time between live updates in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between purges of staged deletes whose grace period is over
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppMode {
@@ -26,11 +38,52 @@ pub struct DeleteProgress {
     pub total_files: u64,
     pub current_file: String,
     pub status: String,
+    pub started: Instant,
+    // Abort requested, waiting for the thread to stop
+    pub cancelling: bool,
+}
+
+impl DeleteProgress {
+    /// Share of the work done, by bytes or by entries when there are no bytes to free
+    pub fn ratio(&self) -> f64 {
+        let (done, total) = if self.total_bytes > 0 {
+            (self.deleted_bytes, self.total_bytes)
+        } else {
+            (self.deleted_files, self.total_files)
+        };
+        if total == 0 {
+            return 0.0;
+        }
+        (done as f64 / total as f64).min(1.0)
+    }
+
+    /// Bytes freed per second so far
+    pub fn throughput(&self) -> f64 {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            self.deleted_bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Time left at the average rate so far, None until anything is done
+    pub fn eta(&self) -> Option<Duration> {
+        let ratio = self.ratio();
+        if ratio <= 0.0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64(elapsed * (1.0 - ratio) / ratio))
+    }
 }
 
 pub struct App {
-    pub _root_path: PathBuf,
     pub current_path: PathBuf,
+    // Directory the entries were built for, None before the first listing
+    pub listed_path: Option<PathBuf>,
+    // Scanned directory tree, navigation walks this instead of rescanning
+    pub tree: Option<DirTree>,
     pub entries: Vec<DirEntry>,
     pub selected_index: usize,
     pub scroll_offset: usize,
@@ -39,19 +92,54 @@ pub struct App {
     pub delete_progress: Option<DeleteProgress>,
     pub delete_thread: Option<JoinHandle<Result<(), String>>>,
     pub delete_rx: Option<mpsc::Receiver<DeleteProgressUpdate>>,
+    // Set to stop the running deletion between files
+    pub delete_cancel: Option<Arc<AtomicBool>>,
     pub notification: Option<String>,
     pub notification_time: Option<Instant>,
     pub show_help: bool,
+    // Paths that could not be read, shown in the errors panel
+    pub scan_errors: Vec<ScanError>,
+    pub show_errors: bool,
+    // Size history of the selected entry
+    pub show_history: bool,
+    pub errors_scroll: usize,
     // Async scanning
     pub scan_thread: Option<JoinHandle<()>>,
     pub scan_rx: Option<mpsc::Receiver<ScanResult>>,
     pub is_scanning: bool,
+    pub scan_cancel: Option<Arc<AtomicBool>>,
+    pub scan_cancelled: bool,
     pub scanning_name: Option<String>,
     pub scan_progress: Option<(usize, usize)>, // (scanned, total)
+    pub scan_options: ScanOptions,
+    // Apparent size vs allocated disk usage
+    pub size_mode: SizeMode,
+    // Sort and colour by size or by item count
+    pub sort_mode: SortMode,
     // Size cache for performance
     pub size_cache: SizeCache,
+    // Earlier snapshots of the current directory, not including this visit
+    pub history: DirectoryHistory,
+    // Compare with the newest snapshot taken at or before this time, None for the latest
+    pub baseline_time: Option<i64>,
+    // List entries removed since that snapshot as ghost rows
+    pub show_removed: bool,
+    pub removed_count: usize,
+    // Hours a permanent delete can be undone before it is purged, 0 deletes right away
+    pub undo_grace_hours: u64,
+    // Background purge of expired staged deletes
+    pub purge_thread: Option<JoinHandle<()>>,
+    pub last_purge: Option<Instant>,
     // Disk space info
     pub disk_space: Option<DiskSpace>,
+    // Live updates from inotify, None when watch mode is off
+    pub watcher: Option<Watcher>,
+    pub last_watch_update: Instant,
+    // Changed directories waiting for a rescan, and those being rescanned
+    pub watch_pending: Vec<PathBuf>,
+    pub watch_rescan: Option<Vec<PathBuf>>,
+    // Kept across live rescans instead of starting threads for each
+    pub watch_pool: Option<Arc<rayon::ThreadPool>>,
 }
 
 pub enum ScanResult {
@@ -60,13 +148,18 @@ pub enum ScanResult {
         scanned_count: usize,
         total_count: usize,
     },
-    Success(Vec<DirEntry>),
+    Success {
+        path: PathBuf,
+        node: Node,
+        errors: Vec<ScanError>,
+    },
+    // Live rescans of changed directories in watch mode, applied in place
+    Updated(Vec<(PathBuf, scan::ScanOutput)>),
     Error(String),
 }
 
 pub enum DeleteProgressUpdate {
-    // Progress updates during deletion (future enhancement)
-    #[allow(dead_code)]
+    // Streamed by permanent deletes a few times per second
     Progress {
         bytes_done: u64,
         bytes_total: u64,
@@ -77,22 +170,31 @@ pub enum DeleteProgressUpdate {
     Complete {
         total_bytes: u64,
         total_files: u64,
+        method: DeleteMethod,
+    },
+    // Stopped on request after removing this much
+    Cancelled {
+        total_bytes: u64,
+        total_files: u64,
     },
     Error(String),
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(scan_options: ScanOptions) -> Self {
         let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
-        Self::new_with_root(root)
+        Self::new_with_root(root, scan_options)
     }
 
-    pub fn new_with_root(root: PathBuf) -> Self {
+    pub fn new_with_root(root: PathBuf, scan_options: ScanOptions) -> Self {
         let disk_space = platform::get_disk_space(&root);
+        // Totals from earlier sessions make unchanged subtrees instant
+        let size_cache = SizeCache::load(&get_cache_path(), &scan_options.cache_settings());
 
         let mut app = App {
-            _root_path: root.clone(),
-            current_path: root.clone(),
+            current_path: root,
+            listed_path: None,
+            tree: None,
             entries: Vec::new(),
             selected_index: 0,
             scroll_offset: 0,
@@ -101,16 +203,38 @@ impl App {
             delete_progress: None,
             delete_thread: None,
             delete_rx: None,
+            delete_cancel: None,
             notification: None,
             notification_time: None,
             show_help: false,
+            scan_errors: Vec::new(),
+            show_errors: false,
+            show_history: false,
+            errors_scroll: 0,
             scan_thread: None,
             scan_rx: None,
             is_scanning: false,
+            scan_cancel: None,
+            scan_cancelled: false,
             scanning_name: None,
             scan_progress: None,
-            size_cache: SizeCache::new(),
+            scan_options,
+            size_mode: SizeMode::default(),
+            sort_mode: SortMode::default(),
+            size_cache,
+            history: DirectoryHistory::default(),
+            baseline_time: None,
+            show_removed: false,
+            removed_count: 0,
+            undo_grace_hours: undo::DEFAULT_GRACE_HOURS,
+            purge_thread: None,
+            last_purge: None,
             disk_space,
+            watcher: None,
+            last_watch_update: Instant::now(),
+            watch_pending: Vec::new(),
+            watch_rescan: None,
+            watch_pool: None,
         };
         app.refresh();
         app
@@ -150,12 +274,21 @@ impl App {
 
     pub fn enter_directory(&mut self) {
         if let Some(entry) = self.entries.get(self.selected_index) {
-            if entry.is_dir {
-                self.current_path = entry.path.clone();
-                self.selected_index = 0;
-                self.scroll_offset = 0;
-                self.disk_space = platform::get_disk_space(&self.current_path);
-                self.refresh();
+            if entry.other_fs {
+                self.notification = Some(format!(
+                    "{} is on another filesystem (skipped by -x)",
+                    entry.display_name()
+                ));
+                self.notification_time = Some(Instant::now());
+            } else if entry.excluded {
+                self.notification = Some(format!(
+                    "{} is excluded from scanning",
+                    entry.display_name()
+                ));
+                self.notification_time = Some(Instant::now());
+            } else if entry.is_dir {
+                let path = entry.path.clone();
+                self.navigate_to(path);
             }
         }
     }
@@ -163,31 +296,71 @@ impl App {
     pub fn go_parent(&mut self) {
         if let Some(parent) = self.current_path.parent() {
             if parent != self.current_path {
-                self.current_path = parent.to_path_buf();
-                self.selected_index = 0;
-                self.scroll_offset = 0;
-                self.disk_space = platform::get_disk_space(&self.current_path);
-                self.refresh();
+                let parent = parent.to_path_buf();
+                self.navigate_to(parent);
             }
         }
     }
 
-    pub fn refresh(&mut self) {
-        // Cancel any existing scan
-        if let Some(thread) = self.scan_thread.take() {
-            let _ = thread.join();
+    /// Show `path`, straight from the tree if it has been scanned already
+    fn navigate_to(&mut self, path: PathBuf) {
+        // Whatever was being scanned is no longer wanted
+        self.stop_scan();
+        self.current_path = path;
+        self.selected_index = 0;
+        self.scroll_offset = 0;
+        self.disk_space = platform::get_disk_space(&self.current_path);
+
+        // Subtrees restored from the size cache still need their listing read,
+        // and those cut short by the file limit or a cancelled scan are incomplete
+        let in_tree = self.tree.as_ref().is_some_and(|tree| {
+            tree.find(&self.current_path)
+                .is_some_and(|node| !node.from_cache && !node.partial)
+        });
+        if in_tree {
+            self.load_entries();
+        } else {
+            self.refresh();
         }
-        self.scan_rx = None;
+    }
+
+    pub fn refresh(&mut self) {
+        // Cancel any existing scan without waiting for its thread
+        self.stop_scan();
+        self.scan_cancelled = false;
 
         // Start async scan
         let path = self.current_path.clone();
         let cache = self.size_cache.clone();
+        let previous = self.previous_node(&path);
+        let counted_links = self
+            .tree
+            .as_ref()
+            .map(|tree| tree.counted_links_outside(&path))
+            .unwrap_or_default();
+        let options = self.scan_options.clone();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
         let (tx, rx) = mpsc::channel();
 
         let tx_clone = tx.clone();
         let handle = thread::spawn(move || {
-            let result = match scan::scan_directory(&path, &cache, Some(&tx_clone)) {
-                Ok(entries) => ScanResult::Success(entries),
+            let result = match scan::scan_tree(
+                &path,
+                &cache,
+                previous.as_ref(),
+                counted_links,
+                &options,
+                &thread_cancel,
+                Some(&tx_clone),
+            ) {
+                // An aborted walk is incomplete, nobody is listening anymore
+                Ok(_) if thread_cancel.load(Ordering::Relaxed) => return,
+                Ok(output) => ScanResult::Success {
+                    path,
+                    node: output.node,
+                    errors: output.errors,
+                },
                 Err(e) => ScanResult::Error(e.to_string()),
             };
             let _ = tx_clone.send(result);
@@ -195,113 +368,449 @@ impl App {
 
         self.scan_thread = Some(handle);
         self.scan_rx = Some(rx);
+        self.scan_cancel = Some(cancel);
         self.is_scanning = true;
     }
 
+    /// Abort the running scan at the user's request
+    pub fn cancel_scan(&mut self) {
+        if self.is_scanning {
+            self.stop_scan();
+            self.scan_cancelled = true;
+            // The scan was for a directory not listed yet: go back to the one
+            // the entries belong to, or show nothing rather than stale entries
+            if self.listed_path.as_ref() != Some(&self.current_path) {
+                match self.listed_path.clone() {
+                    Some(path) => {
+                        self.current_path = path;
+                        self.disk_space = platform::get_disk_space(&self.current_path);
+                    }
+                    None => self.entries.clear(),
+                }
+            }
+            self.notification = Some("⊘ Scan cancelled".to_string());
+            self.notification_time = Some(Instant::now());
+        }
+    }
+
+    /// Signal the scan thread to stop and detach from it
+    fn stop_scan(&mut self) {
+        if let Some(cancel) = self.scan_cancel.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        // Interrupted live rescans are redone on the next watch update
+        if let Some(dirs) = self.watch_rescan.take() {
+            self.watch_pending.extend(dirs);
+        }
+        self.finish_scan();
+    }
+
+    /// Previously scanned state for `path`, so unchanged subtrees can be reused.
+    /// When scanning above the current tree root, the old tree is grafted in
+    /// as a descendant of the new root.
+    fn previous_node(&self, path: &Path) -> Option<Node> {
+        let tree = self.tree.as_ref()?;
+        if let Some(node) = tree.find(path) {
+            return Some(node.clone());
+        }
+
+        let relative = tree.root_path.strip_prefix(path).ok()?;
+        let mut names: Vec<OsString> = relative
+            .components()
+            .map(|c| c.as_os_str().to_os_string())
+            .collect();
+
+        let mut node = tree.root.clone();
+        node.name = names.pop()?;
+        while let Some(name) = names.pop() {
+            node = Node::dir(name, vec![node]);
+        }
+        Some(Node::dir(path.as_os_str().to_os_string(), vec![node]))
+    }
+
+    /// Persist the size cache for the next session
+    pub fn save_cache(&self) -> std::io::Result<()> {
+        self.size_cache.save(&get_cache_path())
+    }
+
     pub fn hard_refresh(&mut self) {
-        // Clear cache and refresh
-        self.size_cache.clear();
-        self.notification = Some("✓ Cache cleared - rescanning...".to_string());
+        // Drop cached totals for this directory and below, then rescan it
+        self.size_cache.invalidate_subtree(&self.current_path);
+        self.notification = Some("✓ Cache cleared for this directory - rescanning...".to_string());
         self.notification_time = Some(Instant::now());
         self.refresh();
     }
 
     pub fn update_scan_progress(&mut self) {
+        let mut results = Vec::new();
         if let Some(rx) = self.scan_rx.as_ref() {
             // Drain all available messages
-            loop {
-                match rx.try_recv() {
-                    Ok(result) => {
-                        match result {
-                            ScanResult::Progress {
-                                current_name,
-                                scanned_count,
-                                total_count,
-                            } => {
-                                // Update current scanning info
-                                self.scanning_name = Some(current_name);
-                                self.scan_progress = Some((scanned_count, total_count));
-                            }
-                            ScanResult::Success(mut entries) => {
-                                self.is_scanning = false;
-                                self.scan_thread = None;
-                                self.scan_rx = None;
-                                self.scanning_name = None;
-                                self.scan_progress = None;
-
-                                // Load previous fingerprint and detect changes
-                                let fp_path = get_fingerprint_path(&self.current_path);
-
-                                // Create fingerprint from scanned entries (without re-reading metadata)
-                                let new_fp = DirectoryFingerprint::from_entries(&entries);
-                                if let Ok(old_fp) = DirectoryFingerprint::load(&fp_path) {
-                                    let changes = old_fp.get_changes(&new_fp);
-
-                                    apply_size_changes(&mut entries, &changes);
-                                }
-
-                                // Save new fingerprint for next run
-                                let _ = std::fs::create_dir_all(fp_path.parent().unwrap());
-                                let _ = new_fp.save(&fp_path);
-
-                                // Always keep the list size-sorted for easier scanning
-                                entries.sort_by(|a, b| b.size.cmp(&a.size));
-
-                                // Don't show parent entry if we're at root
-                                if self
-                                    .current_path
-                                    .parent()
-                                    .map_or(false, |p| p != self.current_path)
-                                {
-                                    let parent_entry = DirEntry {
-                                        path: self.current_path.parent().unwrap().to_path_buf(),
-                                        name: "..".to_string(),
-                                        size: 0,
-                                        is_dir: true,
-                                        file_count: 0,
-                                        size_change: None,
-                                        is_new: false,
-                                    };
-                                    entries.insert(0, parent_entry);
-                                }
-                                self.entries = entries;
-                                self.selected_index = 0;
-                                self.scroll_offset = 0;
-                                // Clear any previous error
-                                if self
-                                    .notification
-                                    .as_ref()
-                                    .map_or(false, |n| n.contains("✗"))
-                                {
-                                    self.notification = None;
-                                }
-                                break; // Exit loop on success
-                            }
-                            ScanResult::Error(e) => {
-                                self.is_scanning = false;
-                                self.scan_thread = None;
-                                self.scan_rx = None;
-                                self.scanning_name = None;
-                                self.scan_progress = None;
-                                self.entries.clear();
-                                self.selected_index = 0;
-                                self.scroll_offset = 0;
-                                self.notification =
-                                    Some(format!("✗ Error reading directory: {}", e));
-                                self.notification_time = Some(Instant::now());
-                                break; // Exit loop on error
-                            }
-                        }
+            while let Ok(result) = rx.try_recv() {
+                results.push(result);
+            }
+        }
+
+        for result in results {
+            match result {
+                ScanResult::Progress {
+                    current_name,
+                    scanned_count,
+                    total_count,
+                } => {
+                    // Update current scanning info
+                    self.scanning_name = Some(current_name);
+                    self.scan_progress = Some((scanned_count, total_count));
+                }
+                ScanResult::Success { path, node, errors } => {
+                    self.finish_scan();
+                    self.apply_scan(path, node, errors);
+                    self.load_entries();
+                    // Clear any previous error
+                    if self.notification.as_ref().is_some_and(|n| n.contains('✗')) {
+                        self.notification = None;
+                    }
+                }
+                ScanResult::Updated(outputs) => {
+                    self.finish_scan();
+                    self.watch_rescan = None;
+                    for (path, output) in outputs {
+                        self.apply_scan(path, output.node, output.errors);
                     }
-                    Err(_) => break, // No more messages
+                    self.disk_space = platform::get_disk_space(&self.current_path);
+                    self.reload_entries();
+                    self.sync_watches();
                 }
+                ScanResult::Error(e) => {
+                    self.finish_scan();
+                    self.entries.clear();
+                    self.selected_index = 0;
+                    self.scroll_offset = 0;
+                    self.notification = Some(format!("✗ Error reading directory: {}", e));
+                    self.notification_time = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    /// Merge a finished scan of `path` into the tree
+    fn apply_scan(&mut self, path: PathBuf, node: Node, errors: Vec<ScanError>) {
+        // Errors below the rescanned path are replaced by the new ones
+        self.scan_errors.retain(|e| !e.path.starts_with(&path));
+        self.scan_errors.extend(errors);
+        self.scan_errors.sort_by(|a, b| a.path.cmp(&b.path));
+        self.errors_scroll = 0;
+
+        // Graft the rescanned subtree into the tree, or start a new tree
+        match self.tree.as_mut() {
+            Some(tree) if tree.find(&path).is_some() => {
+                tree.replace(&path, node);
+            }
+            _ => self.tree = Some(DirTree::new(path, node)),
+        }
+    }
+
+    pub fn toggle_watch(&mut self) {
+        if self.watcher.take().is_some() {
+            self.watch_pending.clear();
+            self.watch_pool = None;
+            self.notification = Some("Watch mode off".to_string());
+        } else {
+            match Watcher::new() {
+                Ok(watcher) => {
+                    self.watcher = Some(watcher);
+                    self.sync_watches();
+                    self.notification = Some("👁 Watching for changes".to_string());
+                }
+                Err(e) => self.notification = Some(format!("✗ Cannot watch: {}", e)),
+            }
+        }
+        self.notification_time = Some(Instant::now());
+    }
+
+    /// Point the watcher at the current directory and its scanned subtree
+    fn sync_watches(&mut self) {
+        if let (Some(watcher), Some(tree)) = (self.watcher.as_mut(), self.tree.as_ref()) {
+            if let Some(node) = tree.find(&self.current_path) {
+                watcher.watch_tree(&self.current_path, node);
+            }
+        }
+    }
+
+    /// Rescan directories reported as changed in the background, their sizes
+    /// are updated in place when the results come in
+    pub fn update_watch(&mut self) {
+        // A running scan will pick the changes up anyway
+        if self.is_scanning
+            || self.watch_rescan.is_some()
+            || self.last_watch_update.elapsed() < WATCH_INTERVAL
+        {
+            return;
+        }
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        self.watch_pending.extend(watcher.changed_dirs());
+        self.last_watch_update = Instant::now();
+        if self.watch_pending.is_empty() {
+            return;
+        }
+
+        let pool = match self.watch_pool.clone() {
+            Some(pool) => pool,
+            None => match rayon::ThreadPoolBuilder::new()
+                .num_threads(self.scan_options.threads)
+                .build()
+            {
+                Ok(pool) => Arc::clone(self.watch_pool.insert(Arc::new(pool))),
+                Err(_) => return,
+            },
+        };
+        let Some(tree) = self.tree.as_ref() else {
+            return;
+        };
+        let changed = std::mem::take(&mut self.watch_pending);
+
+        // Files written in place don't bump the directory mtime, so drop those
+        // entries explicitly; ancestors revalidate through their child references
+        for dir in &changed {
+            self.size_cache.invalidate(dir);
+        }
+
+        // Directories that vanished are picked up by their parent's rescan
+        let dirs = watch::outermost(changed);
+        let jobs: Vec<_> = dirs
+            .iter()
+            .filter_map(|dir| {
+                let previous = tree.find(dir)?.clone();
+                Some((dir.clone(), previous, tree.counted_links_outside(dir)))
+            })
+            .collect();
+
+        let cache = self.size_cache.clone();
+        let options = self.scan_options.clone();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
+        let (tx, rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let outputs = pool.install(|| {
+                jobs.into_iter()
+                    .filter_map(|(dir, previous, counted_links)| {
+                        let output = scan::scan_tree(
+                            &dir,
+                            &cache,
+                            Some(&previous),
+                            counted_links,
+                            &options,
+                            &thread_cancel,
+                            None,
+                        )
+                        .ok()?;
+                        Some((dir, output))
+                    })
+                    .collect()
+            });
+            // An aborted rescan is incomplete and is redone later
+            if !thread_cancel.load(Ordering::Relaxed) {
+                let _ = tx.send(ScanResult::Updated(outputs));
+            }
+        });
+
+        self.scan_thread = Some(handle);
+        self.scan_rx = Some(rx);
+        self.scan_cancel = Some(cancel);
+        self.watch_rescan = Some(dirs);
+    }
+
+    /// Free the space of staged deletes whose grace period is over, on start
+    /// and then every few minutes while mcdu stays open
+    pub fn update_purge(&mut self) {
+        if let Some(thread) = self.purge_thread.take_if(|t| t.is_finished()) {
+            let _ = thread.join();
+            self.disk_space = platform::get_disk_space(&self.current_path);
+        }
+        if self.purge_thread.is_some()
+            || self
+                .last_purge
+                .is_some_and(|t| t.elapsed() < PURGE_INTERVAL)
+        {
+            return;
+        }
+        self.last_purge = Some(Instant::now());
+        let grace_secs = self.undo_grace_hours as i64 * 3600;
+        self.purge_thread = Some(thread::spawn(move || {
+            let _ = undo::purge(Some(grace_secs));
+        }));
+    }
+
+    fn finish_scan(&mut self) {
+        self.is_scanning = false;
+        self.scan_thread = None;
+        self.scan_rx = None;
+        self.scanning_name = None;
+        self.scan_progress = None;
+    }
+
+    /// Show the entries of a newly opened or rescanned directory
+    fn load_entries(&mut self) {
+        self.scan_cancelled = false;
+        self.build_entries(true);
+        self.selected_index = 0;
+        self.scroll_offset = 0;
+        self.sync_watches();
+    }
+
+    /// Rebuild the entries after a live update, keeping the selected entry
+    fn reload_entries(&mut self) {
+        let selected = self
+            .entries
+            .get(self.selected_index)
+            .map(|entry| entry.path.clone());
+        // Keep the same baseline and don't record another snapshot
+        self.build_entries(false);
+        self.selected_index = selected
+            .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+            .unwrap_or_else(|| {
+                self.selected_index
+                    .min(self.entries.len().saturating_sub(1))
+            });
+    }
+
+    /// Rebuild the visible entry list for the current directory from the tree
+    fn build_entries(&mut self, save_fingerprint: bool) {
+        let mut entries = self
+            .tree
+            .as_ref()
+            .and_then(|tree| tree.entries(&self.current_path))
+            .unwrap_or_default();
+        self.listed_path = Some(self.current_path.clone());
+
+        // Load earlier snapshots and detect changes against the chosen one
+        if save_fingerprint {
+            self.history = DirectoryHistory::load(&self.current_path);
+        }
+
+        // Create fingerprint from scanned entries (without re-reading metadata)
+        let new_fp = DirectoryFingerprint::from_entries(&entries);
+        let mut ghosts = Vec::new();
+        if let Some(old_fp) = self.history.baseline(self.baseline_time) {
+            let changes = old_fp.get_changes(&new_fp);
+
+            apply_size_changes(&mut entries, &changes);
+            ghosts = removed_entries(&self.current_path, &changes);
+        }
+        self.removed_count = ghosts.len();
+        if self.show_removed {
+            entries.append(&mut ghosts);
+        }
+
+        // Add this visit to the history for next run
+        if save_fingerprint {
+            let mut history = self.history.clone();
+            history.record(new_fp);
+            let _ = history.save(&self.current_path);
+        }
+
+        // Don't show parent entry if we're at root
+        if let Some(parent) = self.current_path.parent() {
+            if parent != self.current_path {
+                let parent_entry = DirEntry {
+                    path: parent.to_path_buf(),
+                    name: OsString::from(".."),
+                    size: 0,
+                    disk_size: 0,
+                    shared_size: 0,
+                    is_dir: true,
+                    file_count: 0,
+                    dir_count: 0,
+                    symlink_count: 0,
+                    partial: false,
+                    other_fs: false,
+                    excluded: false,
+                    error_count: 0,
+                    mtime: 0,
+                    size_change: None,
+                    is_new: false,
+                    removed: false,
+                };
+                entries.insert(0, parent_entry);
             }
         }
+        self.entries = entries;
+        self.sort_entries();
+    }
+
+    /// Keep the list sorted largest first (by size or item count), with ".." on top
+    /// and removed entries at the bottom
+    fn sort_entries(&mut self) {
+        let size_mode = self.size_mode;
+        let sort_mode = self.sort_mode;
+        self.entries.sort_by_key(|entry| {
+            let key = match sort_mode {
+                _ if entry.removed => entry.size,
+                SortMode::Size => entry.size_for(size_mode),
+                SortMode::Items => entry.item_count(),
+            };
+            (entry.name != "..", entry.removed, std::cmp::Reverse(key))
+        });
+    }
+
+    pub fn toggle_size_mode(&mut self) {
+        self.size_mode = self.size_mode.toggle();
+        self.sort_entries();
+        self.selected_index = 0;
+        self.scroll_offset = 0;
+    }
+
+    pub fn toggle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.toggle();
+        self.sort_entries();
+        self.selected_index = 0;
+        self.scroll_offset = 0;
+    }
+
+    /// Step the change indicators back to the next older snapshot,
+    /// wrapping around to the latest one after the oldest
+    pub fn cycle_baseline(&mut self) {
+        let snapshots = &self.history.snapshots;
+        let current = self.history.baseline_index(self.baseline_time);
+        self.baseline_time = match current {
+            Some(i) if i > 0 => Some(snapshots[i - 1].timestamp),
+            _ => None,
+        };
+
+        let message = match self.history.baseline(self.baseline_time) {
+            None => "No earlier scans of this directory".to_string(),
+            Some(snapshot) if self.baseline_time.is_none() => {
+                format!(
+                    "Comparing with the last scan ({})",
+                    format_timestamp(snapshot.timestamp)
+                )
+            }
+            Some(snapshot) => {
+                format!(
+                    "Comparing with the scan of {}",
+                    format_timestamp(snapshot.timestamp)
+                )
+            }
+        };
+        self.notification = Some(message);
+        self.notification_time = Some(Instant::now());
+        self.reload_entries();
     }
 
     pub fn open_delete_modal(&mut self) {
-        if let Some(entry) = self.entries.get(self.selected_index) {
-            self.modal = Some(Modal::confirm_delete(&entry.path, entry.size));
+        if let Some(entry) = self.entries.get(self.selected_index).filter(|e| !e.removed) {
+            let size = if self.size_mode.is_bytes() {
+                entry.size_for(self.size_mode)
+            } else {
+                entry.size
+            };
+            let totals = DeleteTotals {
+                bytes: entry.size,
+                files: entry.item_count() + u64::from(entry.is_dir),
+            };
+            self.modal = Some(Modal::confirm_delete(&entry.path, size, totals));
         }
     }
 
@@ -309,24 +818,82 @@ impl App {
         self.show_help = !self.show_help;
     }
 
-    pub fn start_delete(&mut self, path: &PathBuf) -> Result<(), String> {
-        let path_clone = path.clone();
+    pub fn toggle_errors(&mut self) {
+        self.show_errors = !self.show_errors;
+        self.errors_scroll = 0;
+    }
+
+    pub fn toggle_removed(&mut self) {
+        self.show_removed = !self.show_removed;
+        if self.show_removed && self.removed_count == 0 {
+            self.notification = Some("Nothing was removed since the compared scan".to_string());
+            self.notification_time = Some(Instant::now());
+        }
+        self.reload_entries();
+    }
+
+    pub fn toggle_history(&mut self) {
+        self.show_history = !self.show_history && self.selected_timeline().is_some();
+    }
+
+    /// The selected entry with its size in each earlier snapshot and now
+    pub fn selected_timeline(&self) -> Option<(&DirEntry, Vec<(i64, u64)>)> {
+        let entry = self
+            .entries
+            .get(self.selected_index)
+            .filter(|e| e.name != "..")?;
+        let mut points = self.history.timeline(&entry.name);
+        let size = if entry.removed { 0 } else { entry.size };
+        points.push((chrono::Utc::now().timestamp(), size));
+        Some((entry, points))
+    }
+
+    pub fn scroll_errors(&mut self, down: bool) {
+        if down {
+            if self.errors_scroll + 1 < self.scan_errors.len() {
+                self.errors_scroll += 1;
+            }
+        } else {
+            self.errors_scroll = self.errors_scroll.saturating_sub(1);
+        }
+    }
+
+    pub fn start_delete(
+        &mut self,
+        path: &Path,
+        totals: DeleteTotals,
+        method: DeleteMethod,
+    ) -> Result<(), String> {
+        // Permanent deletes stay undoable for the grace period, where they can be staged
+        let method = match method {
+            DeleteMethod::Permanent if self.undo_grace_hours > 0 && undo::can_stage(path) => {
+                DeleteMethod::Staged
+            }
+            method => method,
+        };
+        let path_clone = path.to_path_buf();
         let (tx, rx) = mpsc::channel();
         let start_time = Instant::now();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
 
         let handle = thread::spawn(move || {
-            match delete::delete_directory(&path_clone) {
+            match delete::remove(&path_clone, method, totals, Some(&tx), &thread_cancel) {
                 Ok(result) => {
                     let duration_ms = start_time.elapsed().as_millis() as u64;
 
                     // Log the deletion
                     let log = logger::DeleteLog {
                         timestamp: Local::now().to_rfc3339(),
-                        action: "delete".to_string(),
+                        action: method.action().to_string(),
                         path: path_clone.display().to_string(),
                         size_bytes: result.total_bytes,
                         dry_run: false,
-                        status: "success".to_string(),
+                        status: match method {
+                            _ if result.cancelled => "cancelled".to_string(),
+                            DeleteMethod::Staged => "staged".to_string(),
+                            _ => "success".to_string(),
+                        },
                         files_deleted: result.total_files,
                         duration_ms,
                         errors: if result.errors.is_empty() {
@@ -334,13 +901,22 @@ impl App {
                         } else {
                             Some(result.errors)
                         },
+                        moved_to: result.moved_to.map(|p| p.display().to_string()),
                     };
 
                     let _ = logger::write_log(&log);
 
-                    let _ = tx.send(DeleteProgressUpdate::Complete {
-                        total_bytes: result.total_bytes,
-                        total_files: result.total_files,
+                    let _ = tx.send(if result.cancelled {
+                        DeleteProgressUpdate::Cancelled {
+                            total_bytes: result.total_bytes,
+                            total_files: result.total_files,
+                        }
+                    } else {
+                        DeleteProgressUpdate::Complete {
+                            total_bytes: result.total_bytes,
+                            total_files: result.total_files,
+                            method,
+                        }
                     });
                     Ok(())
                 }
@@ -348,7 +924,7 @@ impl App {
                     // Log the error
                     let log = logger::DeleteLog {
                         timestamp: Local::now().to_rfc3339(),
-                        action: "delete".to_string(),
+                        action: method.action().to_string(),
                         path: path_clone.display().to_string(),
                         size_bytes: 0,
                         dry_run: false,
@@ -356,6 +932,7 @@ impl App {
                         files_deleted: 0,
                         duration_ms: start_time.elapsed().as_millis() as u64,
                         errors: Some(vec![e.to_string()]),
+                        moved_to: None,
                     };
 
                     let _ = logger::write_log(&log);
@@ -367,20 +944,77 @@ impl App {
 
         self.delete_thread = Some(handle);
         self.delete_rx = Some(rx);
+        self.delete_cancel = Some(cancel);
         self.mode = AppMode::Deleting;
         self.delete_progress = Some(DeleteProgress {
             deleted_bytes: 0,
-            total_bytes: 0, // Will be updated as we scan
+            total_bytes: 0, // Known once the tree has been walked
             deleted_files: 0,
             total_files: 0,
             current_file: String::new(),
-            status: "Starting deletion...".to_string(),
+            started: Instant::now(),
+            cancelling: false,
+            status: match method {
+                DeleteMethod::Trash => "Moving to trash...".to_string(),
+                DeleteMethod::Staged => "Deleting...".to_string(),
+                DeleteMethod::Permanent => "Counting files...".to_string(),
+            },
         });
 
         Ok(())
     }
 
-    pub fn start_dry_run(&mut self, path: &PathBuf) -> Result<(), String> {
+    /// Ask the running deletion to stop before its next file
+    pub fn cancel_delete(&mut self) {
+        if let Some(cancel) = &self.delete_cancel {
+            cancel.store(true, Ordering::Relaxed);
+            if let Some(progress) = &mut self.delete_progress {
+                progress.status = "Cancelling...".to_string();
+                progress.cancelling = true;
+            }
+        }
+    }
+
+    /// Put back the most recently deleted or trashed entry
+    pub fn undo_last(&mut self) {
+        let undo = match undo::undo(1) {
+            Ok(undo) => undo,
+            Err(e) => {
+                self.notification = Some(format!("✗ Undo failed: {}", e));
+                self.notification_time = Some(Instant::now());
+                return;
+            }
+        };
+        // Newer deletions that can't be restored are named, not hidden
+        let skipped = undo.skipped.first().map(|(transaction, error)| {
+            format!(
+                "couldn't restore {}: {}",
+                transaction.original_path().display(),
+                error
+            )
+        });
+        let message = match (undo.restored.first(), skipped) {
+            (Some(transaction), skipped) => {
+                let path = transaction.original_path();
+                // Its directory's mtime changed, but be explicit like after a delete
+                if let Some(parent) = path.parent() {
+                    self.size_cache.invalidate(parent);
+                }
+                self.disk_space = platform::get_disk_space(&self.current_path);
+                self.refresh();
+                match skipped {
+                    Some(skipped) => format!("↶ Restored {} ({})", path.display(), skipped),
+                    None => format!("↶ Restored {}", path.display()),
+                }
+            }
+            (None, Some(skipped)) => format!("✗ Undo failed, {}", skipped),
+            (None, None) => "Nothing to undo".to_string(),
+        };
+        self.notification = Some(message);
+        self.notification_time = Some(Instant::now());
+    }
+
+    pub fn start_dry_run(&mut self, path: &Path) -> Result<(), String> {
         match delete::dry_run_delete(path) {
             Ok(files) => {
                 self.mode = AppMode::DryRun;
@@ -409,6 +1043,7 @@ impl App {
                     files_deleted: files.len() as u64,
                     duration_ms: 0,
                     errors: None,
+                    moved_to: None,
                 };
 
                 let _ = logger::write_log(&log);
@@ -420,6 +1055,24 @@ impl App {
         }
     }
 
+    /// Leave deleting mode once the delete thread has reported back
+    fn finish_delete(&mut self) {
+        self.delete_progress = None;
+        self.delete_rx = None;
+        self.delete_cancel = None;
+        self.mode = AppMode::Browsing;
+    }
+
+    /// Rescan after entries below the current directory were removed
+    fn refresh_after_delete(&mut self) {
+        // The deleted entry was in this directory; ancestors miss through
+        // their child references and siblings keep their cached totals
+        self.size_cache.invalidate(&self.current_path);
+        // Update disk space after deletion
+        self.disk_space = platform::get_disk_space(&self.current_path);
+        self.refresh();
+    }
+
     pub fn update_delete_progress(&mut self) {
         let mut updates = Vec::new();
         if let Some(rx) = self.delete_rx.as_mut() {
@@ -443,33 +1096,61 @@ impl App {
                         progress.deleted_files = files_done;
                         progress.total_files = files_total;
                         progress.current_file = current_file;
-                        progress.status = "Deleting...".to_string();
+                        if !progress.cancelling {
+                            progress.status = "Deleting...".to_string();
+                        }
                     }
                 }
                 DeleteProgressUpdate::Complete {
                     total_bytes,
                     total_files,
+                    method,
                 } => {
-                    self.delete_progress = None;
-                    self.delete_rx = None;
-                    self.mode = AppMode::Browsing;
+                    self.finish_delete();
+                    let (verb, hint) = match method {
+                        DeleteMethod::Trash => ("Moved to trash:", " - [u] to undo".to_string()),
+                        DeleteMethod::Staged => (
+                            "Deleted",
+                            format!(
+                                " - [u] to undo, space is freed in {}h",
+                                self.undo_grace_hours
+                            ),
+                        ),
+                        // Only without a grace period or when staging wasn't possible
+                        DeleteMethod::Permanent if self.undo_grace_hours > 0 => (
+                            "Deleted",
+                            " - not undoable, no staging area on this filesystem".to_string(),
+                        ),
+                        DeleteMethod::Permanent => ("Deleted", String::new()),
+                    };
                     let msg = format!(
-                        "✓ Deleted {} files ({:.1} MB)",
+                        "✓ {} {} files ({:.1} MB){}",
+                        verb,
+                        total_files,
+                        total_bytes as f64 / 1_000_000.0,
+                        hint
+                    );
+                    self.notification = Some(msg);
+                    self.notification_time = Some(Instant::now());
+                    self.refresh_after_delete();
+                }
+                DeleteProgressUpdate::Cancelled {
+                    total_bytes,
+                    total_files,
+                } => {
+                    self.finish_delete();
+                    let msg = format!(
+                        "⊘ Deletion cancelled after {} files ({:.1} MB)",
                         total_files,
                         total_bytes as f64 / 1_000_000.0
                     );
                     self.notification = Some(msg);
                     self.notification_time = Some(Instant::now());
-                    // Clear cache since directory sizes have changed
-                    self.size_cache.clear();
-                    // Update disk space after deletion
-                    self.disk_space = platform::get_disk_space(&self.current_path);
-                    self.refresh();
+                    // Show what is left of the partly deleted entry
+                    self.refresh_after_delete();
                 }
                 DeleteProgressUpdate::Error(e) => {
-                    self.delete_progress = None;
-                    self.delete_rx = None;
-                    self.mode = AppMode::Browsing;
+                    self.finish_delete();
                     let msg = format!("✗ Delete error: {}", e);
                     self.notification = Some(msg);
                     self.notification_time = Some(Instant::now());
@@ -479,15 +1160,32 @@ impl App {
     }
 }
 
+/// Local date and time of a snapshot, e.g. "2025-01-10 14:23"
+pub fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 fn apply_size_changes(entries: &mut [DirEntry], changes: &[SizeChange]) {
     if entries.is_empty() || changes.is_empty() {
         return;
     }
 
     let total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+    let by_name: HashMap<&OsStr, &SizeChange> =
+        changes.iter().map(|c| (c.name.as_os_str(), c)).collect();
 
     for entry in entries {
-        if let Some(change) = changes.iter().find(|c| c.name == entry.name) {
+        if let Some(change) = by_name.get(entry.name.as_os_str()) {
+            if change.kind == ChangeKind::Added {
+                entry.is_new = true;
+                continue;
+            }
             let percent_of_directory = if total_size > 0 {
                 (change.delta_bytes as f64 / total_size as f64) * 100.0
             } else {
@@ -499,6 +1197,33 @@ fn apply_size_changes(entries: &mut [DirEntry], changes: &[SizeChange]) {
     }
 }
 
+/// Ghost rows for entries of `dir` that are gone since the compared scan
+fn removed_entries(dir: &Path, changes: &[SizeChange]) -> Vec<DirEntry> {
+    changes
+        .iter()
+        .filter(|change| change.kind == ChangeKind::Removed)
+        .map(|change| DirEntry {
+            path: dir.join(&change.name),
+            name: change.name.clone(),
+            size: change.old_size,
+            disk_size: 0,
+            shared_size: 0,
+            is_dir: false,
+            file_count: 0,
+            dir_count: 0,
+            symlink_count: 0,
+            partial: false,
+            other_fs: false,
+            excluded: false,
+            error_count: 0,
+            mtime: 0,
+            size_change: Some((change.delta_bytes, 0.0)),
+            is_new: false,
+            removed: true,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -506,12 +1231,22 @@ mod tests {
     fn mock_entry(name: &str, size: u64) -> DirEntry {
         DirEntry {
             path: PathBuf::from(format!("/{}", name)),
-            name: name.to_string(),
+            name: name.into(),
             size,
+            disk_size: size,
+            shared_size: 0,
             is_dir: true,
             file_count: 0,
+            dir_count: 0,
+            symlink_count: 0,
+            partial: false,
+            other_fs: false,
+            excluded: false,
+            error_count: 0,
+            mtime: 0,
             size_change: None,
             is_new: false,
+            removed: false,
         }
     }
 
@@ -520,6 +1255,7 @@ mod tests {
         let mut entries = vec![mock_entry("a", 60), mock_entry("b", 40)];
         let changes = vec![SizeChange {
             name: "a".into(),
+            kind: ChangeKind::Resized,
             old_size: 30,
             new_size: 90,
             delta_bytes: 30,
@@ -539,6 +1275,7 @@ mod tests {
         let mut entries = vec![mock_entry("empty", 0)];
         let changes = vec![SizeChange {
             name: "empty".into(),
+            kind: ChangeKind::Resized,
             old_size: 0,
             new_size: 100,
             delta_bytes: 100,
@@ -551,4 +1288,22 @@ mod tests {
         assert_eq!(change.0, 100);
         assert_eq!(change.1, 0.0);
     }
+
+    #[test]
+    fn added_entries_are_new_and_removed_become_ghosts() {
+        let mut entries = vec![mock_entry("fresh", 20)];
+        let mut old = DirectoryFingerprint::new();
+        old.entries.insert("gone".into(), (500, 0));
+        let changes = old.get_changes(&DirectoryFingerprint::from_entries(&entries));
+
+        apply_size_changes(&mut entries, &changes);
+        assert!(entries[0].is_new);
+        assert!(entries[0].size_change.is_none());
+
+        let ghosts = removed_entries(Path::new("/data"), &changes);
+        assert_eq!(ghosts.len(), 1);
+        assert_eq!(ghosts[0].path, PathBuf::from("/data/gone"));
+        assert_eq!(ghosts[0].size, 500);
+        assert!(ghosts[0].removed);
+    }
 }
@@ -1,68 +1,553 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::scan::bytes_to_os_string;
+use crate::tree::Totals;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, Metadata};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+
+/// Bumped whenever the on-disk layout changes; older files are ignored
+const CACHE_VERSION: u32 = 4;
+const CACHE_MAGIC: &[u8; 8] = b"MCDUSIZE";
+
+/// Default `--cache-entries`, roughly 100 MB of memory in the worst case
+pub const DEFAULT_MAX_ENTRIES: usize = 500_000;
+
+/// Identity of a directory that survives renames: (device, inode)
+pub type CacheKey = (u64, u64);
+
+/// Counters shown in the title bar
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
 
 #[derive(Clone)]
 pub struct CachedSize {
-    pub size: u64,
-    pub mtime: SystemTime,
+    /// Files and symlinks directly in the directory, plus its own blocks
+    pub own: Totals,
+    /// Names of measured subdirectories, whose totals are cached separately
+    pub children: Vec<OsString>,
+    /// Directory mtime (seconds, nanoseconds) when the entry was recorded
+    pub mtime: (i64, i64),
+    /// `file_signature` sum over the files and symlinks directly in the
+    /// directory, which change size without touching the directory mtime
+    pub files: u64,
 }
 
+/// Directory totals keyed by device+inode, validated by mtime and the sizes
+/// and mtimes of the files inside, LRU bounded. Entries refer to their
+/// subdirectories, so a change anywhere below invalidates every ancestor.
 #[derive(Clone)]
 pub struct SizeCache {
-    cache: Arc<Mutex<HashMap<PathBuf, CachedSize>>>,
+    cache: Arc<Mutex<Lru>>,
+    // Hash of the scan settings the totals were computed with
+    settings: u64,
+}
+
+/// Entries plus their recency: `order` maps a use tick to its key,
+/// so the first entry of `order` is the least recently used
+struct Lru {
+    entries: HashMap<CacheKey, (CachedSize, u64)>,
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    max_entries: usize,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn get(&mut self, key: &CacheKey) -> Option<&CachedSize> {
+        let (_, last_used) = self.entries.get(key)?;
+        self.order.remove(last_used);
+        self.tick += 1;
+        self.order.insert(self.tick, *key);
+        let (entry, last_used) = self.entries.get_mut(key)?;
+        *last_used = self.tick;
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: CacheKey, entry: CachedSize) {
+        self.remove(&key);
+        self.tick += 1;
+        self.order.insert(self.tick, key);
+        self.entries.insert(key, (entry, self.tick));
+        self.evict();
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<CachedSize> {
+        let (entry, last_used) = self.entries.remove(key)?;
+        self.order.remove(&last_used);
+        Some(entry)
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
 }
 
 impl SizeCache {
     pub fn new() -> Self {
         SizeCache {
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+                // Unbounded until the caller sets a budget
+                max_entries: usize::MAX,
+                stats: CacheStats::default(),
+            })),
+            settings: 0,
+        }
+    }
+
+    /// Load the cache saved by a previous session. Totals computed with
+    /// different scan settings (excludes, -x) or an older format are dropped.
+    pub fn load(path: &Path, settings: &str) -> Self {
+        let mut cache = SizeCache::new();
+        cache.settings = fnv1a(settings.as_bytes());
+        if let Ok(entries) = read_cache_file(path, cache.settings) {
+            // Saved least recently used first, so recency survives the round trip
+            let mut lru = cache.cache.lock().unwrap();
+            for (key, entry) in entries {
+                lru.insert(key, entry);
+            }
         }
+        cache
     }
 
-    pub fn get(&self, path: &PathBuf) -> Option<u64> {
-        let cache = self.cache.lock().unwrap();
+    /// Shrink or grow the budget, evicting least recently used entries
+    pub fn set_max_entries(&self, max_entries: usize) {
+        let mut lru = self.cache.lock().unwrap();
+        lru.max_entries = max_entries;
+        lru.evict();
+    }
 
-        if let Some(cached) = cache.get(path) {
-            // Validate cache by checking directory mtime
-            if let Ok(metadata) = std::fs::metadata(path) {
-                if let Ok(current_mtime) = metadata.modified() {
-                    // If directory hasn't been modified, cache is still valid
-                    if current_mtime == cached.mtime {
-                        return Some(cached.size);
-                    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a crash can't leave a torn cache
+        let tmp = path.with_extension("tmp");
+        {
+            let lru = self.cache.lock().unwrap();
+            let mut out = BufWriter::new(fs::File::create(&tmp)?);
+            out.write_all(CACHE_MAGIC)?;
+            out.write_all(&CACHE_VERSION.to_le_bytes())?;
+            out.write_all(&self.settings.to_le_bytes())?;
+            out.write_all(&(lru.entries.len() as u64).to_le_bytes())?;
+            for &(dev, ino) in lru.order.values() {
+                let (cached, _) = &lru.entries[&(dev, ino)];
+                let t = &cached.own;
+                for value in [
+                    dev,
+                    ino,
+                    cached.mtime.0 as u64,
+                    cached.mtime.1 as u64,
+                    t.size,
+                    t.disk_size,
+                    t.shared_size,
+                    t.file_count,
+                    t.dir_count,
+                    t.symlink_count,
+                    cached.files,
+                ] {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+                out.write_all(&(cached.children.len() as u32).to_le_bytes())?;
+                for name in &cached.children {
+                    let bytes = name.as_encoded_bytes();
+                    out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+                    out.write_all(bytes)?;
                 }
             }
+            out.flush()?;
         }
+        fs::rename(tmp, path)
+    }
+
+    /// Recursive totals for the directory at `path`, if nothing in it or in
+    /// any directory below it has been modified since they were cached
+    pub fn get(&self, path: &Path, metadata: &Metadata) -> Option<Totals> {
+        let totals = self.lookup(path, metadata);
+        let mut lru = self.cache.lock().unwrap();
+        if totals.is_some() {
+            lru.stats.hits += 1;
+        } else {
+            lru.stats.misses += 1;
+        }
+        totals
+    }
+
+    fn lookup(&self, path: &Path, metadata: &Metadata) -> Option<Totals> {
+        let key = cache_key(metadata)?;
+        let (mut totals, children, files) = {
+            let mut lru = self.cache.lock().unwrap();
+            let cached = lru.get(&key)?;
+            if cached.mtime != mtime_of(metadata) {
+                return None;
+            }
+            (cached.own, cached.children.clone(), cached.files)
+        };
+        if files_signature(path).ok()? != files {
+            return None;
+        }
+
+        // Revalidate down the tree, without holding the lock while stat'ing
+        for name in children {
+            let child_path = path.join(&name);
+            let child_metadata = fs::symlink_metadata(&child_path)
+                .ok()
+                .filter(|m| m.is_dir())?;
+            totals.add(&self.lookup(&child_path, &child_metadata)?);
+            totals.dir_count += 1;
+        }
+        Some(totals)
+    }
+
+    /// Record a directory's own totals, the names of its subdirectories and
+    /// the `file_signature` sum of its other entries
+    pub fn set(&self, metadata: &Metadata, own: Totals, children: Vec<OsString>, files: u64) {
+        if let Some(key) = cache_key(metadata) {
+            let mut lru = self.cache.lock().unwrap();
+            lru.insert(
+                key,
+                CachedSize {
+                    own,
+                    children,
+                    mtime: mtime_of(metadata),
+                    files,
+                },
+            );
+        }
+    }
 
-        None
+    pub fn stats(&self) -> CacheStats {
+        let lru = self.cache.lock().unwrap();
+        CacheStats {
+            entries: lru.entries.len(),
+            ..lru.stats
+        }
     }
 
-    pub fn set(&self, path: PathBuf, size: u64) {
-        let mut cache = self.cache.lock().unwrap();
+    /// Forget one directory; its ancestors revalidate through it and miss too
+    pub fn invalidate(&self, path: &Path) {
+        if let Some(key) = fs::symlink_metadata(path).ok().and_then(|m| cache_key(&m)) {
+            self.cache.lock().unwrap().remove(&key);
+        }
+    }
 
-        // Get directory mtime for validation
-        if let Ok(metadata) = std::fs::metadata(&path) {
-            if let Ok(mtime) = metadata.modified() {
-                cache.insert(path, CachedSize { size, mtime });
+    /// Forget a directory and every cached directory below it
+    pub fn invalidate_subtree(&self, path: &Path) {
+        let Some(key) = fs::symlink_metadata(path).ok().and_then(|m| cache_key(&m)) else {
+            return;
+        };
+        let removed = self.cache.lock().unwrap().remove(&key);
+        if let Some(entry) = removed {
+            for name in entry.children {
+                self.invalidate_subtree(&path.join(name));
             }
         }
     }
+}
 
-    pub fn clear(&self) {
-        let mut cache = self.cache.lock().unwrap();
-        cache.clear();
+fn read_cache_file(path: &Path, settings: u64) -> io::Result<Vec<(CacheKey, CachedSize)>> {
+    let mut input = BufReader::new(fs::File::open(path)?);
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    let version = read_u32(&mut input)?;
+    if &magic != CACHE_MAGIC || version != CACHE_VERSION || read_u64(&mut input)? != settings {
+        return Ok(Vec::new());
     }
 
-    pub fn size(&self) -> usize {
-        let cache = self.cache.lock().unwrap();
-        cache.len()
+    let count = read_u64(&mut input)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut values = [0u64; 11];
+        for value in values.iter_mut() {
+            *value = read_u64(&mut input)?;
+        }
+        let [dev, ino, secs, nanos, size, disk_size, shared_size, file_count, dir_count, symlink_count, files] =
+            values;
+        // Counts and lengths aren't trusted for allocations, a short file is an error
+        let mut children = Vec::new();
+        for _ in 0..read_u32(&mut input)? {
+            children.push(bytes_to_os_string(read_bytes(&mut input)?));
+        }
+        entries.push((
+            (dev, ino),
+            CachedSize {
+                own: Totals {
+                    size,
+                    disk_size,
+                    shared_size,
+                    file_count,
+                    dir_count,
+                    symlink_count,
+                    error_count: 0,
+                },
+                children,
+                mtime: (secs as i64, nanos as i64),
+                files,
+            },
+        ));
     }
+    Ok(entries)
+}
+
+pub fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Length-prefixed bytes, allocating only as much as the input really holds
+pub fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(input)?;
+    let mut bytes = Vec::new();
+    input
+        .by_ref()
+        .take(u64::from(len))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Location of the persistent size cache
+pub fn get_cache_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home)
+        .join(".mcdu")
+        .join("cache")
+        .join("sizes.bin")
+}
+
+/// Hash of one non-directory entry's name, size and mtime. Entries are
+/// combined by adding their hashes, so listing order doesn't matter.
+pub fn file_signature(name: &OsStr, metadata: &Metadata) -> u64 {
+    let (secs, nanos) = mtime_of(metadata);
+    let mut bytes = name.as_encoded_bytes().to_vec();
+    bytes.extend_from_slice(&metadata.len().to_le_bytes());
+    bytes.extend_from_slice(&secs.to_le_bytes());
+    bytes.extend_from_slice(&nanos.to_le_bytes());
+    fnv1a(&bytes)
+}
+
+/// `file_signature` sum over the entries of `dir` that aren't directories
+fn files_signature(dir: &Path) -> io::Result<u64> {
+    let mut signature = 0u64;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            signature =
+                signature.wrapping_add(file_signature(&entry.file_name(), &entry.metadata()?));
+        }
+    }
+    Ok(signature)
+}
+
+/// 64-bit FNV-1a, stable across runs and Rust versions (unlike `DefaultHasher`)
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(unix)]
+fn cache_key(metadata: &Metadata) -> Option<CacheKey> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Without stable inode numbers there is nothing safe to key on
+#[cfg(not(unix))]
+fn cache_key(_metadata: &Metadata) -> Option<CacheKey> {
+    None
+}
+
+#[cfg(unix)]
+fn mtime_of(metadata: &Metadata) -> (i64, i64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.mtime(), metadata.mtime_nsec())
+}
+
+#[cfg(not(unix))]
+fn mtime_of(_metadata: &Metadata) -> (i64, i64) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_cache_loads_only_with_same_settings() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        let metadata = fs::metadata(&dir).unwrap();
+        let totals = Totals {
+            size: 1234,
+            file_count: 3,
+            ..Default::default()
+        };
+
+        let cache_path = tmp.path().join("sizes.bin");
+        let cache = SizeCache::load(&tmp.path().join("missing.bin"), "-x");
+        cache.set(&metadata, totals, Vec::new(), 0);
+        cache.save(&cache_path).unwrap();
+
+        let loaded = SizeCache::load(&cache_path, "-x");
+        assert_eq!(loaded.get(&dir, &metadata), Some(totals));
+        let other = SizeCache::load(&cache_path, "");
+        assert_eq!(other.stats().entries, 0);
+
+        // Touching the directory makes the entry stale
+        fs::write(dir.join("new"), b"").unwrap();
+        assert_eq!(loaded.get(&dir, &fs::metadata(&dir).unwrap()), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn corrupt_lengths_load_as_empty_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let metadata = fs::metadata(&dir).unwrap();
+        let cache = SizeCache::load(&dir.join("missing.bin"), "");
+        cache.set(&metadata, Totals::default(), vec!["sub".into()], 0);
+        let path = dir.join("sizes.bin");
+        cache.save(&path).unwrap();
+
+        // Claim 4 billion children and a 4 GiB name where the file ends after 3 bytes
+        let mut bytes = fs::read(&path).unwrap();
+        let end = bytes.len();
+        bytes[end - 11..end - 3].copy_from_slice(&[0xff; 8]);
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(SizeCache::load(&path, "").stats().entries, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nested_change_invalidates_ancestors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        let meta = |p: &Path| fs::metadata(p).unwrap();
+        let own = |size| Totals {
+            size,
+            ..Default::default()
+        };
+
+        let cache = SizeCache::new();
+        cache.set(&meta(&root.join("a/b")), own(10), Vec::new(), 0);
+        cache.set(&meta(&root.join("a")), own(5), vec!["b".into()], 0);
+        cache.set(&meta(&root), own(1), vec!["a".into()], 0);
+
+        let totals = cache.get(&root, &meta(&root)).unwrap();
+        assert_eq!(totals.size, 16);
+        assert_eq!(totals.dir_count, 2);
+
+        // Only a/b's mtime changes, but the root total must not be served
+        fs::write(root.join("a/b/new"), b"data").unwrap();
+        assert_eq!(cache.get(&root, &meta(&root)), None);
+        assert_eq!(cache.get(&root.join("a"), &meta(&root.join("a"))), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_growing_deep_down_invalidates_ancestors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/log"), b"one line\n").unwrap();
+        let meta = |p: &Path| fs::metadata(p).unwrap();
+        let signed = |p: &Path| files_signature(p).unwrap();
+
+        let cache = SizeCache::new();
+        let b = root.join("a/b");
+        cache.set(&meta(&b), Totals::default(), Vec::new(), signed(&b));
+        cache.set(
+            &meta(&root.join("a")),
+            Totals::default(),
+            vec!["b".into()],
+            0,
+        );
+        cache.set(&meta(&root), Totals::default(), vec!["a".into()], 0);
+        assert!(cache.get(&root, &meta(&root)).is_some());
+
+        // Appending leaves every directory mtime alone
+        let dir_mtime = meta(&b).modified().unwrap();
+        let mut log = fs::OpenOptions::new()
+            .append(true)
+            .open(b.join("log"))
+            .unwrap();
+        log.write_all(b"another line\n").unwrap();
+        assert_eq!(meta(&b).modified().unwrap(), dir_mtime);
+        assert_eq!(cache.get(&root, &meta(&root)), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn budget_evicts_least_recently_used() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for name in ["a", "b", "c"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+        }
+        let meta = |name: &str| fs::metadata(root.join(name)).unwrap();
+        let cache = SizeCache::new();
+        cache.set_max_entries(2);
+        cache.set(&meta("a"), Totals::default(), Vec::new(), 0);
+        cache.set(&meta("b"), Totals::default(), Vec::new(), 0);
+
+        // Using "a" makes "b" the oldest entry
+        assert!(cache.get(&root.join("a"), &meta("a")).is_some());
+        cache.set(&meta("c"), Totals::default(), Vec::new(), 0);
+        assert!(cache.get(&root.join("b"), &meta("b")).is_none());
+        assert!(cache.get(&root.join("a"), &meta("a")).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn subtree_invalidation_keeps_siblings() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        let meta = |p: &str| fs::metadata(root.join(p)).unwrap();
+        let cache = SizeCache::new();
+        cache.set(&meta("a/b"), Totals::default(), Vec::new(), 0);
+        cache.set(&meta("a"), Totals::default(), vec!["b".into()], 0);
+        cache.set(&meta("c"), Totals::default(), Vec::new(), 0);
 
-    #[allow(dead_code)]
-    pub fn invalidate(&self, path: &PathBuf) {
-        let mut cache = self.cache.lock().unwrap();
-        cache.remove(path);
+        cache.invalidate_subtree(&root.join("a"));
+        assert_eq!(cache.stats().entries, 1);
+        assert!(cache.get(&root.join("c"), &meta("c")).is_some());
     }
 }
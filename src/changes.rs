@@ -1,20 +1,52 @@
+use crate::cache::{fnv1a, read_bytes, read_u32, read_u64};
+use crate::scan::bytes_to_os_string;
 use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::Write;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
+/// Bumped whenever the history file layout changes; older files are ignored
+const HISTORY_VERSION: u32 = 1;
+const HISTORY_MAGIC: &[u8; 8] = b"MCDUHIST";
+
+/// Snapshots kept per directory, the oldest is dropped first
+const MAX_SNAPSHOTS: usize = 30;
+
+/// A new snapshot replaces the latest one if that is younger than this,
+/// so browsing back and forth doesn't push older scans out of the history
+const SNAPSHOT_INTERVAL_SECS: i64 = 60 * 60;
+
 /// Represents a snapshot of directory state using hashes instead of paths
 #[derive(Debug, Clone)]
 pub struct DirectoryFingerprint {
-    /// Hash of (name + size) for each entry - compact bitmap-like representation
-    pub entries: HashMap<String, (u64, u64)>, // name -> (size, mtime)
+    /// When the snapshot was taken, seconds since the epoch
+    pub timestamp: i64,
+    /// Size and modification time of each entry, by name
+    pub entries: HashMap<OsString, (u64, u64)>, // name -> (size, mtime)
+}
+
+/// Rolling history of snapshots for one directory, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryHistory {
+    pub snapshots: Vec<DirectoryFingerprint>,
+    // Migrated from an `fp_*.txt` file, which is removed on the next save
+    legacy: bool,
+}
+
+/// How an entry differs from the previous scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Resized,
+    Added,
+    Removed,
 }
 
 /// Delta between current and previous scan
 #[derive(Debug, Clone)]
 pub struct SizeChange {
-    pub name: String,
-    #[allow(dead_code)]
+    pub name: OsString,
+    pub kind: ChangeKind,
     pub old_size: u64,
     #[allow(dead_code)]
     pub new_size: u64,
@@ -23,9 +55,30 @@ pub struct SizeChange {
     pub delta_percent: f32,
 }
 
+impl SizeChange {
+    fn new(name: &OsStr, kind: ChangeKind, old_size: u64, new_size: u64) -> Self {
+        let delta = new_size as i64 - old_size as i64;
+        let percent = if old_size > 0 {
+            (delta as f32 / old_size as f32) * 100.0
+        } else {
+            100.0
+        };
+
+        SizeChange {
+            name: name.to_os_string(),
+            kind,
+            old_size,
+            new_size,
+            delta_bytes: delta,
+            delta_percent: percent,
+        }
+    }
+}
+
 impl DirectoryFingerprint {
     pub fn new() -> Self {
         DirectoryFingerprint {
+            timestamp: chrono::Utc::now().timestamp(),
             entries: HashMap::new(),
         }
     }
@@ -39,7 +92,7 @@ impl DirectoryFingerprint {
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let metadata = entry.metadata()?;
-            let name = entry.file_name().into_string().unwrap_or_default();
+            let name = entry.file_name();
 
             let mtime = metadata
                 .modified()?
@@ -60,9 +113,8 @@ impl DirectoryFingerprint {
         let mut fp = DirectoryFingerprint::new();
 
         for entry in entries {
-            // Use mtime=0 as a placeholder for now since we have sizes
-            // The important part for change detection is the size, not mtime
-            fp.entries.insert(entry.name.clone(), (entry.size, 0));
+            fp.entries
+                .insert(entry.name.clone(), (entry.size, entry.mtime));
         }
 
         fp
@@ -72,71 +124,239 @@ impl DirectoryFingerprint {
     pub fn get_changes(&self, other: &DirectoryFingerprint) -> Vec<SizeChange> {
         let mut changes = Vec::new();
 
-        // Check for size changes in existing entries
+        // Check for size changes in existing entries, and new entries
         for (name, (new_size, _new_mtime)) in &other.entries {
-            if let Some((old_size, _old_mtime)) = self.entries.get(name) {
-                if old_size != new_size {
-                    let delta = *new_size as i64 - *old_size as i64;
-                    let percent = if *old_size > 0 {
-                        (delta as f32 / *old_size as f32) * 100.0
-                    } else {
-                        100.0
-                    };
-
-                    changes.push(SizeChange {
-                        name: name.clone(),
-                        old_size: *old_size,
-                        new_size: *new_size,
-                        delta_bytes: delta,
-                        delta_percent: percent,
-                    });
+            let (kind, old_size) = match self.entries.get(name) {
+                Some((old_size, _old_mtime)) if old_size != new_size => {
+                    (ChangeKind::Resized, *old_size)
                 }
+                Some(_) => continue,
+                None => (ChangeKind::Added, 0),
+            };
+            changes.push(SizeChange::new(name, kind, old_size, *new_size));
+        }
+
+        // Entries that are gone
+        for (name, (old_size, _old_mtime)) in &self.entries {
+            if !other.entries.contains_key(name) {
+                changes.push(SizeChange::new(name, ChangeKind::Removed, *old_size, 0));
             }
         }
 
         // Sort by absolute delta (largest changes first)
-        changes.sort_by(|a, b| b.delta_bytes.abs().cmp(&a.delta_bytes.abs()));
+        changes.sort_by_key(|c| std::cmp::Reverse(c.delta_bytes.abs()));
 
         changes
     }
+}
+
+impl DirectoryHistory {
+    /// Add a snapshot, replacing the latest one if it is recent and
+    /// dropping the oldest beyond `MAX_SNAPSHOTS`
+    pub fn record(&mut self, snapshot: DirectoryFingerprint) {
+        if self
+            .snapshots
+            .last()
+            .is_some_and(|last| snapshot.timestamp - last.timestamp < SNAPSHOT_INTERVAL_SECS)
+        {
+            self.snapshots.pop();
+        }
+        self.snapshots.push(snapshot);
+        let excess = self.snapshots.len().saturating_sub(MAX_SNAPSHOTS);
+        self.snapshots.drain(..excess);
+    }
 
-    /// Save fingerprint to a simple binary format
-    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        let mut file = fs::File::create(path)?;
+    /// Index of the snapshot to diff against: the newest one taken at or
+    /// before `at`, or the latest for None. Falls back to the oldest when
+    /// every snapshot is newer than `at`.
+    pub fn baseline_index(&self, at: Option<i64>) -> Option<usize> {
+        let last = self.snapshots.len().checked_sub(1)?;
+        let Some(at) = at else {
+            return Some(last);
+        };
+        Some(
+            self.snapshots
+                .iter()
+                .rposition(|s| s.timestamp <= at)
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn baseline(&self, at: Option<i64>) -> Option<&DirectoryFingerprint> {
+        self.baseline_index(at).map(|i| &self.snapshots[i])
+    }
 
-        // Format: simple text-based for debuggability
-        // name:size:mtime\n
-        for (name, (size, mtime)) in &self.entries {
-            writeln!(file, "{}:{}:{}", name, size, mtime)?;
+    /// Size of one entry in every snapshot that has it, as (timestamp, size)
+    pub fn timeline(&self, name: &OsStr) -> Vec<(i64, u64)> {
+        self.snapshots
+            .iter()
+            .filter_map(|s| s.entries.get(name).map(|&(size, _)| (s.timestamp, size)))
+            .collect()
+    }
+
+    /// History of `dir` from `~/.mcdu/cache/history/`, migrating the text
+    /// file of older versions on first use. Empty if there is none.
+    pub fn load(dir: &Path) -> Self {
+        if let Ok(Some(history)) = Self::read_file(&get_history_path(dir), dir) {
+            return history;
         }
+        Self::migrate_legacy(&get_legacy_fingerprint_path(dir), dir).unwrap_or_default()
+    }
 
+    /// Save the history of `dir`, replacing the text file it was migrated from
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        self.write_file(&get_history_path(dir), dir)?;
+        if self.legacy {
+            let _ = fs::remove_file(get_legacy_fingerprint_path(dir));
+        }
         Ok(())
     }
 
-    /// Load fingerprint from saved format
-    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut fp = DirectoryFingerprint::new();
+    /// Take over an older text file if it was written for `dir`. Those files
+    /// don't record their directory and `/a/b.c` shares one with `/a/b/c`,
+    /// so the newest snapshot must name an entry that `dir` still has.
+    fn migrate_legacy(path: &Path, dir: &Path) -> Option<Self> {
+        let mut history = Self::load_legacy(path).ok()?;
+        let newest = history.snapshots.last()?;
+        if !newest
+            .entries
+            .keys()
+            .any(|name| fs::symlink_metadata(dir.join(name)).is_ok())
+        {
+            return None;
+        }
+        history.legacy = true;
+        Some(history)
+    }
+
+    /// Read a history file. None if it belongs to another directory whose
+    /// path hashes the same, or was written by another format version.
+    fn read_file(path: &Path, dir: &Path) -> io::Result<Option<Self>> {
+        let mut input = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != HISTORY_MAGIC || read_u32(&mut input)? != HISTORY_VERSION {
+            return Ok(None);
+        }
+        if read_bytes(&mut input)? != dir.as_os_str().as_encoded_bytes() {
+            return Ok(None);
+        }
+
+        let mut history = DirectoryHistory::default();
+        for _ in 0..read_u32(&mut input)? {
+            let mut snapshot = DirectoryFingerprint {
+                timestamp: read_u64(&mut input)? as i64,
+                entries: HashMap::new(),
+            };
+            for _ in 0..read_u32(&mut input)? {
+                let name = bytes_to_os_string(read_bytes(&mut input)?);
+                let size = read_u64(&mut input)?;
+                let mtime = read_u64(&mut input)?;
+                snapshot.entries.insert(name, (size, mtime));
+            }
+            history.snapshots.push(snapshot);
+        }
+        Ok(Some(history))
+    }
+
+    /// Write a history file: magic, version and the directory path, then
+    /// each snapshot's timestamp and entries. Names are stored as raw
+    /// length-prefixed bytes, integers little endian.
+    fn write_file(&self, path: &Path, dir: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replaced atomically, like the size cache
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(fs::File::create(&tmp)?);
+            out.write_all(HISTORY_MAGIC)?;
+            out.write_all(&HISTORY_VERSION.to_le_bytes())?;
+            write_bytes(&mut out, dir.as_os_str().as_encoded_bytes())?;
+            out.write_all(&(self.snapshots.len() as u32).to_le_bytes())?;
+            for snapshot in &self.snapshots {
+                out.write_all(&(snapshot.timestamp as u64).to_le_bytes())?;
+                out.write_all(&(snapshot.entries.len() as u32).to_le_bytes())?;
+                for (name, (size, mtime)) in &snapshot.entries {
+                    write_bytes(&mut out, name.as_encoded_bytes())?;
+                    out.write_all(&size.to_le_bytes())?;
+                    out.write_all(&mtime.to_le_bytes())?;
+                }
+            }
+            out.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    /// Load the `fp_*.txt` file of v0.2.0: one snapshot of `name:size:mtime`
+    /// lines with unescaped names, dated by the file's mtime
+    fn load_legacy(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut history = DirectoryHistory::default();
 
         if !path.exists() {
-            return Ok(fp); // Return empty if no previous snapshot
+            return Ok(history); // Return empty if no previous snapshot
         }
 
+        let mut snapshot = DirectoryFingerprint {
+            timestamp: legacy_timestamp(path),
+            entries: HashMap::new(),
+        };
         let content = fs::read_to_string(path)?;
         for line in content.lines() {
-            if line.is_empty() {
-                continue;
-            }
-
-            let parts: Vec<&str> = line.split(':').collect();
-            if parts.len() >= 3 {
-                if let (Ok(size), Ok(mtime)) = (parts[1].parse::<u64>(), parts[2].parse::<u64>()) {
-                    fp.entries.insert(parts[0].to_string(), (size, mtime));
+            // Split from the right, so names containing ':' survive
+            let parts: Vec<&str> = line.rsplitn(3, ':').collect();
+            if let [mtime, size, name] = parts[..] {
+                if let (Ok(size), Ok(mtime)) = (size.parse::<u64>(), mtime.parse::<u64>()) {
+                    snapshot.entries.insert(name.into(), (size, mtime));
                 }
             }
         }
 
-        Ok(fp)
+        if !snapshot.entries.is_empty() {
+            history.snapshots.push(snapshot);
+        }
+        Ok(history)
+    }
+}
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+/// Average growth in bytes per day, a least squares fit through the
+/// (timestamp, size) points. None without two points some time apart.
+pub fn growth_per_day(points: &[(i64, u64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let days: Vec<f64> = points.iter().map(|&(t, _)| t as f64 / 86_400.0).collect();
+    let mean_day = days.iter().sum::<f64>() / n;
+    let mean_size = points.iter().map(|&(_, size)| size as f64).sum::<f64>() / n;
+
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (day, &(_, size)) in days.iter().zip(points) {
+        covariance += (day - mean_day) * (size as f64 - mean_size);
+        variance += (day - mean_day).powi(2);
     }
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Days until `available` bytes are used up at `per_day` growth,
+/// None if the entry isn't growing
+pub fn days_until_full(available: u64, per_day: f64) -> Option<f64> {
+    (per_day > 0.0).then(|| available as f64 / per_day)
+}
+
+/// Date of a snapshot file written before the history format existed
+fn legacy_timestamp(path: &Path) -> i64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64)
 }
 
 #[allow(dead_code)]
@@ -151,8 +371,20 @@ fn calculate_dir_size(path: PathBuf) -> u64 {
         .sum()
 }
 
-/// Get the fingerprint file path for a directory
-pub fn get_fingerprint_path(dir_path: &Path) -> PathBuf {
+/// Get the history file path for a directory, named by a hash of its path
+pub fn get_history_path(dir_path: &Path) -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    let hash = fnv1a(dir_path.as_os_str().as_encoded_bytes());
+    PathBuf::from(home)
+        .join(".mcdu")
+        .join("cache")
+        .join("history")
+        .join(format!("{:016x}.bin", hash))
+}
+
+/// Fingerprint file of older versions. Both `/` and `.` map to `_`, so
+/// different directories can share one; only read for migration.
+fn get_legacy_fingerprint_path(dir_path: &Path) -> PathBuf {
     let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
     let cache_dir = PathBuf::from(home).join(".mcdu").join("cache");
 
@@ -172,16 +404,166 @@ mod tests {
     #[test]
     fn test_changes_detection() {
         let mut old = DirectoryFingerprint::new();
-        old.entries.insert("file1".to_string(), (1000, 100));
-        old.entries.insert("file2".to_string(), (2000, 200));
+        old.entries.insert("file1".into(), (1000, 100));
+        old.entries.insert("file2".into(), (2000, 200));
 
         let mut new = DirectoryFingerprint::new();
-        new.entries.insert("file1".to_string(), (2000, 100)); // 1000 bytes larger
-        new.entries.insert("file2".to_string(), (2000, 200)); // no change
+        new.entries.insert("file1".into(), (2000, 100)); // 1000 bytes larger
+        new.entries.insert("file2".into(), (2000, 200)); // no change
 
         let changes = old.get_changes(&new);
         assert_eq!(changes.len(), 1);
         assert_eq!(changes[0].name, "file1");
         assert_eq!(changes[0].delta_bytes, 1000);
     }
+
+    #[test]
+    fn added_and_removed_entries_are_changes() {
+        let mut old = DirectoryFingerprint::new();
+        old.entries.insert("kept".into(), (10, 0));
+        old.entries.insert("gone".into(), (500, 0));
+
+        let mut new = DirectoryFingerprint::new();
+        new.entries.insert("kept".into(), (10, 0));
+        new.entries.insert("fresh".into(), (20, 0));
+
+        let changes = old.get_changes(&new);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].name, "gone");
+        assert_eq!(changes[0].kind, ChangeKind::Removed);
+        assert_eq!(changes[0].old_size, 500);
+        assert_eq!(changes[1].name, "fresh");
+        assert_eq!(changes[1].kind, ChangeKind::Added);
+        assert_eq!(changes[1].delta_bytes, 20);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_and_load_keep_raw_name_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let latin1 = OsStr::from_bytes(b"caf\xe9").to_os_string();
+        let mut fp = DirectoryFingerprint::new();
+        fp.entries.insert(latin1.clone(), (10, 1));
+        fp.entries.insert("100%\nsure".into(), (20, 2));
+        fp.entries.insert("12:30:45.log".into(), (30, 3));
+
+        let mut history = DirectoryHistory::default();
+        history.record(fp);
+
+        let dir = Path::new("/data/b.c");
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("history.bin");
+        history.write_file(&path, dir).unwrap();
+        let loaded = DirectoryHistory::read_file(&path, dir).unwrap().unwrap();
+        // Another directory never reads this history, even under the same file name
+        let other = DirectoryHistory::read_file(&path, Path::new("/data/b/c")).unwrap();
+
+        assert!(other.is_none());
+        let loaded = loaded.baseline(None).unwrap();
+        assert_eq!(loaded.entries.get(&latin1), Some(&(10, 1)));
+        assert_eq!(loaded.entries.get(OsStr::new("100%\nsure")), Some(&(20, 2)));
+        assert_eq!(
+            loaded.entries.get(OsStr::new("12:30:45.log")),
+            Some(&(30, 3))
+        );
+    }
+
+    #[test]
+    fn history_paths_differ_for_dots_and_slashes() {
+        assert_ne!(
+            get_history_path(Path::new("/a/b.c")),
+            get_history_path(Path::new("/a/b/c"))
+        );
+        assert_eq!(
+            get_legacy_fingerprint_path(Path::new("/a/b.c")),
+            get_legacy_fingerprint_path(Path::new("/a/b/c"))
+        );
+    }
+
+    fn snapshot(timestamp: i64, size: u64) -> DirectoryFingerprint {
+        let mut fp = DirectoryFingerprint::new();
+        fp.timestamp = timestamp;
+        fp.entries.insert("log".into(), (size, 0));
+        fp
+    }
+
+    #[test]
+    fn history_keeps_dated_snapshots() {
+        let day = 24 * 60 * 60;
+        let mut history = DirectoryHistory::default();
+        history.record(snapshot(day, 1));
+        history.record(snapshot(2 * day, 2));
+        // Revisiting within the hour replaces the latest snapshot
+        history.record(snapshot(2 * day + 60, 3));
+        assert_eq!(history.snapshots.len(), 2);
+
+        assert_eq!(history.baseline(None).unwrap().timestamp, 2 * day + 60);
+        assert_eq!(history.baseline(Some(2 * day)).unwrap().timestamp, day);
+        assert_eq!(history.baseline(Some(0)).unwrap().timestamp, day);
+
+        for i in 0..MAX_SNAPSHOTS as i64 {
+            history.record(snapshot((3 + i) * day, 0));
+        }
+        assert_eq!(history.snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(history.snapshots[0].timestamp, 3 * day);
+    }
+
+    #[test]
+    fn growth_rate_projects_fill_date() {
+        let day = 24 * 60 * 60;
+        let points = [(0, 1000), (day, 3000), (2 * day, 5000)];
+        let per_day = growth_per_day(&points).unwrap();
+        assert!((per_day - 2000.0).abs() < 1e-6);
+        assert_eq!(days_until_full(10_000, per_day), Some(5.0));
+
+        assert_eq!(growth_per_day(&points[..1]), None);
+        assert_eq!(days_until_full(10_000, -5.0), None);
+    }
+
+    #[test]
+    fn legacy_fingerprint_loads_as_one_snapshot() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fp_old.txt");
+        fs::write(&path, "big.iso:4096:0\nnotes:12:0\n10:15.log:7:0\n").unwrap();
+        let history = DirectoryHistory::load_legacy(&path).unwrap();
+
+        assert_eq!(history.snapshots.len(), 1);
+        assert!(history.snapshots[0].timestamp > 0);
+        assert_eq!(history.snapshots[0].entries.len(), 3);
+        assert_eq!(
+            history.snapshots[0].entries.get(OsStr::new("10:15.log")),
+            Some(&(7, 0))
+        );
+    }
+
+    #[test]
+    fn legacy_names_are_taken_verbatim() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fp_old.txt");
+        fs::write(&path, "My%20File.pdf:10:0\n100%:20:0\n").unwrap();
+        let history = DirectoryHistory::load_legacy(&path).unwrap();
+
+        let entries = &history.snapshots[0].entries;
+        assert_eq!(entries.get(OsStr::new("My%20File.pdf")), Some(&(10, 0)));
+        assert_eq!(entries.get(OsStr::new("100%")), Some(&(20, 0)));
+    }
+
+    #[test]
+    fn legacy_file_migrates_only_to_its_own_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("b.c")).unwrap();
+        fs::create_dir_all(root.join("b/c")).unwrap();
+        fs::write(root.join("b.c/notes"), b"").unwrap();
+        let legacy = root.join("fp_legacy.txt");
+        fs::write(&legacy, "notes:12:0\n").unwrap();
+
+        let other = DirectoryHistory::migrate_legacy(&legacy, &root.join("b/c"));
+        let own = DirectoryHistory::migrate_legacy(&legacy, &root.join("b.c")).unwrap();
+        assert!(other.is_none());
+        assert!(own.legacy);
+        assert!(!DirectoryHistory::default().legacy);
+        assert_eq!(own.snapshots[0].entries.len(), 1);
+    }
 }
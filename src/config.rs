@@ -0,0 +1,34 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+/// Optional settings read from `~/.mcdu/config.json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Globs to skip while scanning, same syntax as `--exclude`
+    pub exclude: Vec<String>,
+    /// Regular expressions to skip while scanning, same as `--exclude-regex`
+    pub exclude_regex: Vec<String>,
+    /// Maximum number of directories in the size cache, same as `--cache-entries`
+    pub cache_entries: Option<usize>,
+    /// Hours a permanent delete can be undone before it is purged, same as `--undo-grace`
+    pub undo_grace_hours: Option<u64>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = get_config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e).into())
+    }
+}
+
+pub fn get_config_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".mcdu").join("config.json")
+}
@@ -1,82 +1,586 @@
+use crate::app::DeleteProgressUpdate;
+#[cfg(unix)]
+use nix::dir::{Dir, Type};
+#[cfg(unix)]
+use nix::fcntl::{AtFlags, OFlag};
+#[cfg(unix)]
+use nix::sys::stat::{fstat, fstatat, Mode, SFlag};
+#[cfg(unix)]
+use nix::unistd::{unlinkat, UnlinkatFlags};
+use rayon::prelude::*;
+#[cfg(unix)]
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::PathBuf;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
+/// How often a running deletion reports its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct DeleteResult {
     pub total_bytes: u64,
     pub total_files: u64,
     pub errors: Vec<String>,
+    /// Where the entry went when it was moved to the trash or staged
+    pub moved_to: Option<PathBuf>,
+    /// Stopped early on request; the totals cover what was removed until then
+    pub cancelled: bool,
+}
+
+/// Size and entry count of a deletion target, as known from the scan
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeleteTotals {
+    pub bytes: u64,
+    /// Entries including the target itself
+    pub files: u64,
 }
 
-pub fn delete_directory(path: &PathBuf) -> Result<DeleteResult, Box<dyn std::error::Error>> {
-    let mut total_bytes = 0u64;
-    let mut total_files = 0u64;
-    let mut errors = Vec::new();
+/// How a confirmed deletion gets rid of its target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeleteMethod {
+    /// Move to the FreeDesktop trash, restorable from any file manager
+    Trash,
+    /// Rename into the staging area, unlinked when the undo grace period ends
+    Staged,
+    /// Unlink everything
+    Permanent,
+}
 
-    // Optimized: Single walk, collect entries with metadata to avoid re-stating
-    struct EntryWithMetadata {
-        path: PathBuf,
-        size: u64,
-        is_file: bool,
+impl DeleteMethod {
+    /// Name of the action in the deletion log
+    pub fn action(&self) -> &'static str {
+        match self {
+            DeleteMethod::Trash => "trash",
+            DeleteMethod::Staged | DeleteMethod::Permanent => "delete",
+        }
     }
+}
 
-    let entries: Vec<EntryWithMetadata> = WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter_map(|entry| {
-            let entry_path = entry.path();
-            if entry_path == path {
-                return None; // Skip root for now
+/// Delete `path` with the given method. Moves report the scanned `totals`,
+/// permanent deletes count what they unlink and stream their progress to
+/// `progress_tx`; setting `cancel` stops between files.
+pub fn remove(
+    path: &PathBuf,
+    method: DeleteMethod,
+    totals: DeleteTotals,
+    progress_tx: Option<&mpsc::Sender<DeleteProgressUpdate>>,
+    cancel: &AtomicBool,
+) -> Result<DeleteResult, Box<dyn std::error::Error>> {
+    match method {
+        DeleteMethod::Trash | DeleteMethod::Staged => move_entry(path, method, totals, cancel),
+        DeleteMethod::Permanent => delete_directory(path, progress_tx, cancel),
+    }
+}
+
+/// Move `path` to the trash or the staging area and journal it for undo
+fn move_entry(
+    path: &Path,
+    method: DeleteMethod,
+    totals: DeleteTotals,
+    cancel: &AtomicBool,
+) -> Result<DeleteResult, Box<dyn std::error::Error>> {
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(DeleteResult {
+            total_bytes: 0,
+            total_files: 0,
+            errors: Vec::new(),
+            moved_to: None,
+            cancelled: true,
+        });
+    }
+    let moved_to = if method == DeleteMethod::Staged {
+        crate::undo::stage(path, totals.bytes, totals.files)?.moved_path()
+    } else {
+        let trashed_to = crate::trash::move_to_trash(path)?;
+        let _ = crate::undo::record_trash(path, &trashed_to, totals.bytes, totals.files);
+        trashed_to
+    };
+    Ok(DeleteResult {
+        total_bytes: totals.bytes,
+        total_files: totals.files,
+        errors: Vec::new(),
+        moved_to: Some(moved_to),
+        cancelled: false,
+    })
+}
+
+/// Unlink `path` and everything below it in parallel, after counting it for
+/// the progress totals. Setting `cancel` stops before the next entry.
+pub fn delete_directory(
+    path: &PathBuf,
+    progress_tx: Option<&mpsc::Sender<DeleteProgressUpdate>>,
+    cancel: &AtomicBool,
+) -> Result<DeleteResult, Box<dyn std::error::Error>> {
+    // A file or symlink on its own
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        fs::remove_file(path)?;
+        return Ok(DeleteResult {
+            total_bytes: metadata.len(),
+            total_files: 1,
+            errors: Vec::new(),
+            moved_to: None,
+            cancelled: false,
+        });
+    }
+
+    let deleter = ParallelDelete {
+        bytes_total: AtomicU64::new(0),
+        // The root directory counts as one more entry
+        files_total: AtomicU64::new(1),
+        bytes_done: AtomicU64::new(0),
+        files_done: AtomicU64::new(0),
+        errors: Mutex::new(Vec::new()),
+        cancel,
+        progress_tx,
+        last_report: Mutex::new(Instant::now()),
+    };
+
+    deleter.visit_root(path, false)?;
+    if !deleter.cancelled() {
+        deleter.report(path);
+        deleter.visit_root(path, true)?;
+    }
+
+    // Finally, remove the root directory itself
+    let cancelled = deleter.cancelled();
+    if !cancelled {
+        match fs::remove_dir(path) {
+            Ok(()) => {
+                deleter.files_done.fetch_add(1, Ordering::Relaxed);
             }
+            Err(e) => deleter.error(path, e),
+        }
+    }
 
-            // Get metadata once and store it
-            entry.metadata().ok().map(|metadata| EntryWithMetadata {
-                path: entry_path.to_path_buf(),
-                size: metadata.len(),
-                is_file: metadata.is_file(),
-            })
-        })
-        .collect();
-
-    // Delete in reverse order (files first, then directories)
-    for entry in entries.iter().rev() {
-        if entry.is_file {
-            if fs::remove_file(&entry.path).is_ok() {
-                total_bytes += entry.size;
-                total_files += 1;
+    Ok(DeleteResult {
+        total_bytes: deleter.bytes_done.into_inner(),
+        total_files: deleter.files_done.into_inner(),
+        errors: deleter.errors.into_inner().unwrap(),
+        moved_to: None,
+        cancelled,
+    })
+}
+
+/// Shared state of a deletion running on the rayon pool
+struct ParallelDelete<'a> {
+    bytes_total: AtomicU64,
+    files_total: AtomicU64,
+    bytes_done: AtomicU64,
+    files_done: AtomicU64,
+    errors: Mutex<Vec<String>>,
+    cancel: &'a AtomicBool,
+    progress_tx: Option<&'a mpsc::Sender<DeleteProgressUpdate>>,
+    last_report: Mutex<Instant>,
+}
+
+#[cfg(unix)]
+impl ParallelDelete<'_> {
+    const DIR_FLAGS: OFlag = OFlag::O_RDONLY
+        .union(OFlag::O_DIRECTORY)
+        .union(OFlag::O_NOFOLLOW)
+        .union(OFlag::O_CLOEXEC);
+
+    /// Count or unlink everything inside the directory at `path`
+    fn visit_root(&self, path: &Path, remove: bool) -> nix::Result<()> {
+        let dir = Dir::open(path, Self::DIR_FLAGS, Mode::empty())?;
+        self.visit(dir, path, remove);
+        Ok(())
+    }
+
+    /// Count (`remove` false) or unlink (`remove` true) everything inside
+    /// `dir`. Files are handled relative to the directory while it is listed.
+    /// Subdirectories are opened relative to it a batch at a time, then it is
+    /// closed while they are visited in parallel, so open fds don't grow with
+    /// depth. It is reopened through the `..` of a visited subdirectory, after
+    /// checking it is still the same directory. Symlinks are never followed
+    /// and nothing is reopened by path. Returns `dir`, or None if it was lost.
+    fn visit(&self, mut dir: Dir, path: &Path, remove: bool) -> Option<Dir> {
+        let subdirs = self.visit_files(&mut dir, path, remove);
+        if subdirs.is_empty() {
+            return Some(dir);
+        }
+        let id = match dir_id(&dir) {
+            Ok(id) => id,
+            Err(e) => {
+                self.error(path, e);
+                return None;
+            }
+        };
+
+        for batch in subdirs.chunks(rayon::current_num_threads().max(1)) {
+            if self.cancelled() {
+                break;
+            }
+            let children: Vec<_> = batch
+                .iter()
+                .map(|name| {
+                    let child = Dir::openat(
+                        Some(dir.as_raw_fd()),
+                        name.as_os_str(),
+                        Self::DIR_FLAGS,
+                        Mode::empty(),
+                    );
+                    (name, child)
+                })
+                .collect();
+
+            // Keep the directory open if there is no subdirectory to come back through
+            let kept = if children.iter().any(|(_, child)| child.is_ok()) {
+                drop(dir);
+                None
             } else {
-                errors.push(format!("Failed to delete {}", entry.path.display()));
+                Some(dir)
+            };
+            let reopened = Mutex::new(None);
+            children.into_par_iter().for_each(|(name, child)| {
+                let child_path = path.join(name);
+                match child {
+                    Ok(child) => {
+                        if let Some(child) = self.visit(child, &child_path, remove) {
+                            let mut reopened = reopened.lock().unwrap();
+                            if reopened.is_none() {
+                                *reopened = self.reopen_parent(&child, &child_path, id);
+                            }
+                        }
+                    }
+                    Err(e) => self.error(&child_path, e),
+                }
+                if !remove {
+                    self.files_total.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+            dir = match kept.or(reopened.into_inner().unwrap()) {
+                Some(dir) => dir,
+                None => {
+                    self.error(path, "lost track of the directory while deleting it");
+                    return None;
+                }
+            };
+
+            // Subdirectories whose contents may be half gone are kept
+            if !remove || self.cancelled() {
+                continue;
+            }
+            for name in batch {
+                match unlinkat(
+                    Some(dir.as_raw_fd()),
+                    name.as_os_str(),
+                    UnlinkatFlags::RemoveDir,
+                ) {
+                    Ok(()) => {
+                        self.files_done.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => self.error(&path.join(name), e),
+                }
+            }
+        }
+        Some(dir)
+    }
+
+    /// Open the parent of `child` through its `..`, provided it is still the
+    /// directory identified by `id` and was not moved away meanwhile
+    fn reopen_parent(&self, child: &Dir, child_path: &Path, id: (u64, u64)) -> Option<Dir> {
+        let parent_path = child_path.parent().unwrap_or(child_path);
+        let parent = match Dir::openat(
+            Some(child.as_raw_fd()),
+            "..",
+            Self::DIR_FLAGS,
+            Mode::empty(),
+        ) {
+            Ok(parent) => parent,
+            Err(e) => {
+                self.error(parent_path, e);
+                return None;
+            }
+        };
+        match dir_id(&parent) {
+            Ok(parent_id) if parent_id == id => Some(parent),
+            Ok(_) => {
+                self.error(
+                    parent_path,
+                    "directory was moved while it was being deleted",
+                );
+                None
+            }
+            Err(e) => {
+                self.error(parent_path, e);
+                None
             }
-        } else {
-            if fs::remove_dir(&entry.path).is_ok() {
-                total_files += 1;
+        }
+    }
+
+    /// Count or unlink the non-directories in `dir`, returning the names of
+    /// its subdirectories
+    fn visit_files(&self, dir: &mut Dir, path: &Path, remove: bool) -> Vec<OsString> {
+        let fd = dir.as_raw_fd();
+        let mut subdirs = Vec::new();
+        for entry in dir.iter() {
+            if self.cancelled() {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.error(path, e);
+                    break;
+                }
+            };
+            let name = OsStr::from_bytes(entry.file_name().to_bytes());
+            if name == "." || name == ".." {
+                continue;
+            }
+
+            let (is_dir, size) = match fstatat(Some(fd), name, AtFlags::AT_SYMLINK_NOFOLLOW) {
+                Ok(stat) => {
+                    let kind = SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT;
+                    let size = if kind == SFlag::S_IFREG {
+                        stat.st_size as u64
+                    } else {
+                        0
+                    };
+                    (kind == SFlag::S_IFDIR, size)
+                }
+                // Gone or unreadable, let the unlink report it
+                Err(_) => (entry.file_type() == Some(Type::Directory), 0),
+            };
+            if is_dir {
+                subdirs.push(name.to_os_string());
+            } else if !remove {
+                self.bytes_total.fetch_add(size, Ordering::Relaxed);
+                self.files_total.fetch_add(1, Ordering::Relaxed);
+            } else if let Err(e) = unlinkat(Some(fd), name, UnlinkatFlags::NoRemoveDir) {
+                self.error(&path.join(name), e);
             } else {
-                errors.push(format!("Failed to delete {}", entry.path.display()));
+                self.bytes_done.fetch_add(size, Ordering::Relaxed);
+                self.files_done.fetch_add(1, Ordering::Relaxed);
+                self.maybe_report(|| path.join(name));
             }
         }
+        subdirs
     }
+}
 
-    // Finally, remove the root directory itself
-    if let Err(e) = fs::remove_dir(path) {
-        errors.push(format!("Failed to remove root directory: {}", e));
-    } else {
-        total_files += 1;
+/// (dev, ino) of an open directory
+#[cfg(unix)]
+fn dir_id(dir: &Dir) -> nix::Result<(u64, u64)> {
+    let stat = fstat(dir.as_raw_fd())?;
+    Ok((stat.st_dev as u64, stat.st_ino as u64))
+}
+
+#[cfg(not(unix))]
+impl ParallelDelete<'_> {
+    fn visit_root(&self, path: &Path, remove: bool) -> std::io::Result<()> {
+        fs::read_dir(path)?;
+        self.visit(path, remove);
+        Ok(())
     }
 
-    Ok(DeleteResult {
-        total_bytes,
-        total_files,
-        errors,
-    })
+    /// Same walk as on Unix, by path
+    fn visit(&self, path: &Path, remove: bool) {
+        let Ok(read_dir) = fs::read_dir(path) else {
+            return;
+        };
+        let mut subdirs = Vec::new();
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            if self.cancelled() {
+                return;
+            }
+            let entry_path = entry.path();
+            let Ok(metadata) = fs::symlink_metadata(&entry_path) else {
+                continue;
+            };
+            let size = if metadata.is_file() {
+                metadata.len()
+            } else {
+                0
+            };
+            if metadata.is_dir() {
+                subdirs.push(entry_path);
+            } else if !remove {
+                self.bytes_total.fetch_add(size, Ordering::Relaxed);
+                self.files_total.fetch_add(1, Ordering::Relaxed);
+            } else if let Err(e) = fs::remove_file(&entry_path) {
+                self.error(&entry_path, e);
+            } else {
+                self.bytes_done.fetch_add(size, Ordering::Relaxed);
+                self.files_done.fetch_add(1, Ordering::Relaxed);
+                self.maybe_report(|| entry_path.clone());
+            }
+        }
+
+        subdirs.par_iter().for_each(|subdir| {
+            if self.cancelled() {
+                return;
+            }
+            self.visit(subdir, remove);
+            if !remove {
+                self.files_total.fetch_add(1, Ordering::Relaxed);
+            } else if self.cancelled() {
+                // Its contents may be half gone, keep the directory
+            } else if let Err(e) = fs::remove_dir(subdir) {
+                self.error(subdir, e);
+            } else {
+                self.files_done.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+impl ParallelDelete<'_> {
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    fn error(&self, path: &Path, error: impl std::fmt::Display) {
+        self.errors
+            .lock()
+            .unwrap()
+            .push(format!("Failed to delete {}: {}", path.display(), error));
+    }
+
+    /// Report progress if the last report is older than `PROGRESS_INTERVAL`.
+    /// Workers that find another one reporting just carry on.
+    fn maybe_report(&self, current_file: impl FnOnce() -> PathBuf) {
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.report(&current_file());
+            *last_report = Instant::now();
+        }
+    }
+
+    fn report(&self, current_file: &Path) {
+        if let Some(tx) = self.progress_tx {
+            let _ = tx.send(DeleteProgressUpdate::Progress {
+                bytes_done: self.bytes_done.load(Ordering::Relaxed),
+                bytes_total: self.bytes_total.load(Ordering::Relaxed),
+                files_done: self.files_done.load(Ordering::Relaxed),
+                files_total: self.files_total.load(Ordering::Relaxed),
+                current_file: current_file.display().to_string(),
+            });
+        }
+    }
 }
 
-pub fn dry_run_delete(path: &PathBuf) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+pub fn dry_run_delete(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
     let mut files = Vec::new();
 
     for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
         files.push(entry.path().to_path_buf());
     }
 
-    files.push(path.clone());
+    files.push(path.to_path_buf());
     Ok(files)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permanent_delete_reports_totals_from_the_walk() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/one"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("a/b/two"), vec![0u8; 50]).unwrap();
+        // Deleted as a link, never followed
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("keep"), b"keep").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, root.join("a/b/link")).unwrap();
+        let link_entries = if cfg!(unix) { 1 } else { 0 };
+
+        let (tx, rx) = mpsc::channel();
+        let result = remove(
+            &root,
+            DeleteMethod::Permanent,
+            DeleteTotals::default(),
+            Some(&tx),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert!(!root.exists());
+        assert!(result.errors.is_empty());
+        assert_eq!(result.total_bytes, 150);
+        assert_eq!(result.total_files, 5 + link_entries);
+        assert!(outside.join("keep").exists());
+
+        let Ok(DeleteProgressUpdate::Progress {
+            bytes_done,
+            bytes_total,
+            files_done,
+            files_total,
+            ..
+        }) = rx.try_recv()
+        else {
+            panic!("no progress before the first unlink");
+        };
+        assert_eq!((bytes_done, files_done), (0, 0));
+        assert_eq!((bytes_total, files_total), (150, 5 + link_entries));
+    }
+
+    #[test]
+    fn cancelled_delete_leaves_the_rest_in_place() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/one"), b"data").unwrap();
+        let cancel = AtomicBool::new(true);
+
+        for method in [DeleteMethod::Staged, DeleteMethod::Permanent] {
+            let result = remove(&root, method, DeleteTotals::default(), None, &cancel).unwrap();
+            assert!(result.cancelled);
+            assert_eq!((result.total_bytes, result.total_files), (0, 0));
+            assert!(result.moved_to.is_none());
+            assert!(root.join("a/one").exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn deep_trees_are_deleted_with_few_open_fds() {
+        // Runs itself again under a low fd limit, deeper than the limit.
+        // The cwd changes, which is fine in a process of its own.
+        if std::env::var_os("MCDU_DEEP_DELETE").is_none() {
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg("ulimit -n 64 && exec \"$0\" --exact --test-threads 1 \"$1\"")
+                .arg(std::env::current_exe().unwrap())
+                .arg("delete::tests::deep_trees_are_deleted_with_few_open_fds")
+                .env("MCDU_DEEP_DELETE", "1")
+                .env("RAYON_NUM_THREADS", "4")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        // Also longer than PATH_MAX, so it has to be built relative to the cwd
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir(&root).unwrap();
+        std::env::set_current_dir(&root).unwrap();
+        for _ in 0..450 {
+            fs::create_dir("directory").unwrap();
+            std::env::set_current_dir("directory").unwrap();
+        }
+        fs::write("f", b"x").unwrap();
+        std::env::set_current_dir(tmp.path()).unwrap();
+
+        let result = delete_directory(&root, None, &AtomicBool::new(false)).unwrap();
+        assert_eq!(result.errors, Vec::<String>::new());
+        assert_eq!(result.total_files, 452);
+        assert!(!root.exists());
+    }
+}
@@ -0,0 +1,191 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of per-directory ignore files, one glob per line
+pub const IGNORE_FILE: &str = ".mcduignore";
+
+/// Exclude rules from the command line and `~/.mcdu/config.json`
+#[derive(Clone, Debug, Default)]
+pub struct ExcludeRules {
+    globs: Patterns,
+    regexes: Vec<Regex>,
+    // The patterns as given, for describe()
+    sources: Vec<String>,
+}
+
+impl ExcludeRules {
+    pub fn new(globs: &[String], regexes: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ExcludeRules {
+            globs: Patterns::new(globs)?,
+            regexes: regexes
+                .iter()
+                .map(|r| Regex::new(r))
+                .collect::<Result<_, _>>()?,
+            sources: globs
+                .iter()
+                .map(|g| format!("glob:{}", g))
+                .chain(regexes.iter().map(|r| format!("regex:{}", r)))
+                .collect(),
+        })
+    }
+
+    /// Stable description of the rules, e.g. "glob:*.log regex:^/tmp"
+    pub fn describe(&self) -> String {
+        self.sources.join(" ")
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        // Path globs on the command line are anchored at the filesystem root
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        if self.globs.matches(path, relative) {
+            return true;
+        }
+        if self.regexes.is_empty() {
+            return false;
+        }
+        let path_str = path.to_string_lossy();
+        self.regexes.iter().any(|r| r.is_match(&path_str))
+    }
+}
+
+/// Glob patterns, gitignore style: patterns without a `/` match the entry
+/// name at any depth, patterns with a `/` match the path relative to a base
+#[derive(Clone, Debug, Default)]
+struct Patterns {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Patterns {
+    fn new(patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_end_matches('/');
+            if pattern.contains('/') {
+                let pattern = pattern.trim_start_matches('/');
+                paths.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+                // A leading "**/" also matches directly below the base
+                if let Some(rest) = pattern.strip_prefix("**/") {
+                    paths.add(GlobBuilder::new(rest).literal_separator(true).build()?);
+                }
+            } else {
+                names.add(Glob::new(pattern)?);
+            }
+        }
+        Ok(Patterns {
+            names: names.build()?,
+            paths: paths.build()?,
+        })
+    }
+
+    fn matches(&self, path: &Path, relative: &Path) -> bool {
+        if let Some(name) = path.file_name() {
+            if self.names.is_match(name) {
+                return true;
+            }
+        }
+        self.paths.is_match(relative)
+    }
+}
+
+/// Rules loaded from one `.mcduignore`, relative to its directory
+#[derive(Debug)]
+struct IgnoreFile {
+    base: PathBuf,
+    patterns: Patterns,
+}
+
+/// The `.mcduignore` files that apply to a directory and its descendants
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreStack {
+    files: Vec<Arc<IgnoreFile>>,
+}
+
+impl IgnoreStack {
+    /// Stack for a scan root, including ignore files of all its ancestors
+    pub fn for_root(root: &Path) -> Self {
+        let mut ancestors: Vec<&Path> = root.ancestors().collect();
+        ancestors.reverse();
+        let mut stack = IgnoreStack::default();
+        for dir in ancestors {
+            stack = stack.enter(dir);
+        }
+        stack
+    }
+
+    /// Stack for the children of `dir`, adding its ignore file if present
+    pub fn enter(&self, dir: &Path) -> Self {
+        let content = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(content) => content,
+            Err(_) => return self.clone(),
+        };
+
+        let lines: Vec<String> = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+
+        // Invalid ignore files are skipped rather than failing the scan
+        let patterns = match Patterns::new(&lines) {
+            Ok(patterns) => patterns,
+            Err(_) => return self.clone(),
+        };
+
+        let mut files = self.files.clone();
+        files.push(Arc::new(IgnoreFile {
+            base: dir.to_path_buf(),
+            patterns,
+        }));
+        IgnoreStack { files }
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.files.iter().any(|file| {
+            path.strip_prefix(&file.base)
+                .is_ok_and(|relative| file.patterns.matches(path, relative))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_globs_match_at_any_depth() {
+        let rules = ExcludeRules::new(&["node_modules".into(), "*.log".into()], &[]).unwrap();
+
+        assert!(rules.is_excluded(Path::new("/src/app/node_modules")));
+        assert!(rules.is_excluded(Path::new("/var/log/syslog.log")));
+        assert!(!rules.is_excluded(Path::new("/src/app/main.rs")));
+    }
+
+    #[test]
+    fn path_globs_and_regexes_match_full_paths() {
+        let rules = ExcludeRules::new(&["/proc/**".into()], &[r"/\.cache/".into()]).unwrap();
+
+        assert!(rules.is_excluded(Path::new("/proc/1/fd")));
+        assert!(rules.is_excluded(Path::new("/home/me/.cache/pip")));
+        assert!(!rules.is_excluded(Path::new("/home/me/proc")));
+    }
+
+    #[test]
+    fn ignore_file_patterns_are_relative_to_their_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        fs::write(dir.join(IGNORE_FILE), "# build output\ntarget\nsub/*.tmp\n").unwrap();
+
+        let stack = IgnoreStack::default().enter(&dir);
+        assert!(stack.is_ignored(&dir.join("target")));
+        assert!(stack.is_ignored(&dir.join("crate/target")));
+        assert!(stack.is_ignored(&dir.join("sub/a.tmp")));
+        assert!(!stack.is_ignored(&dir.join("other/a.tmp")));
+        assert!(!stack.is_ignored(Path::new("/elsewhere/target")));
+    }
+}
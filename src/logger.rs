@@ -16,6 +16,9 @@ pub struct DeleteLog {
     pub duration_ms: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub errors: Option<Vec<String>>,
+    /// Where the entry went when it was moved to the trash or staged for undo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
 }
 
 pub fn write_log(log: &DeleteLog) -> Result<(), Box<dyn std::error::Error>> {
@@ -1,14 +1,21 @@
 mod app;
 mod cache;
 mod changes;
+mod config;
 mod delete;
+mod exclude;
 mod logger;
 mod modal;
 mod platform;
 mod scan;
+mod trash;
+mod tree;
 mod ui;
+mod undo;
+mod watch;
 
 use app::App;
+use clap::Parser;
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent},
     terminal::{disable_raw_mode, enable_raw_mode},
@@ -19,8 +26,91 @@ use std::error::Error;
 use std::io;
 use std::path::PathBuf;
 
+#[derive(Parser)]
+#[command(name = "mcdu", version, about = "Modern disk usage analyzer")]
+struct Cli {
+    /// Directory to analyze (defaults to the current directory)
+    path: Option<PathBuf>,
+
+    /// Number of scanner threads (0 = one per CPU)
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+
+    /// Stop scanning new directories after this many files (sizes become lower bounds)
+    #[arg(long, value_name = "N")]
+    max_files: Option<u64>,
+
+    /// Skip paths matching this glob (repeatable; names match at any depth)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Skip paths matching this regular expression (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    exclude_regex: Vec<String>,
+
+    /// List excluded entries greyed out instead of hiding them
+    #[arg(long)]
+    show_excluded: bool,
+
+    /// Stay on the filesystem of the scanned directory, skipping other mounts
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// Start in disk usage mode (allocated blocks) instead of apparent size
+    #[arg(long)]
+    disk_usage: bool,
+
+    /// Watch the displayed directory and update sizes live (Linux only)
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Start in inode mode, ranking entries by recursive entry count
+    #[arg(long, conflicts_with = "disk_usage")]
+    inodes: bool,
+
+    /// Keep at most this many directories in the size cache (least recently used are evicted)
+    #[arg(long, value_name = "N")]
+    cache_entries: Option<usize>,
+
+    /// Hours a permanent delete can be undone before its space is freed (0 = delete right away)
+    #[arg(long, value_name = "HOURS")]
+    undo_grace: Option<u64>,
+
+    /// Restore the N most recent deletions and trash moves, then exit
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+    undo: Option<usize>,
+
+    /// Free the space of all staged deletions now, then exit
+    #[arg(long, conflicts_with = "undo")]
+    purge_staged: bool,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let start_path = resolve_start_path()?;
+    let cli = Cli::parse();
+    if let Some(count) = cli.undo {
+        return undo_latest(count);
+    }
+    if cli.purge_staged {
+        return purge_staged();
+    }
+    let start_path = resolve_start_path(cli.path)?;
+
+    // Exclude rules from ~/.mcdu/config.json come first, the command line adds to them
+    let config = config::Config::load()?;
+    let globs = [config.exclude, cli.exclude].concat();
+    let regexes = [config.exclude_regex, cli.exclude_regex].concat();
+    let undo_grace_hours = cli
+        .undo_grace
+        .or(config.undo_grace_hours)
+        .unwrap_or(undo::DEFAULT_GRACE_HOURS);
+
+    let scan_options = scan::ScanOptions {
+        threads: cli.threads,
+        max_files: cli.max_files,
+        one_filesystem: cli.one_file_system,
+        exclude: exclude::ExcludeRules::new(&globs, &regexes)?,
+        show_excluded: cli.show_excluded,
+    };
 
     // Setup terminal
     enable_raw_mode()?;
@@ -31,11 +121,23 @@ fn main() -> Result<(), Box<dyn Error>> {
     terminal.hide_cursor()?;
 
     // Run app
-    let app = match start_path {
-        Some(path) => App::new_with_root(path),
-        None => App::new(),
+    let mut app = match start_path {
+        Some(path) => App::new_with_root(path, scan_options),
+        None => App::new(scan_options),
     };
-    let result = run_app(&mut terminal, app);
+    if cli.disk_usage {
+        app.size_mode = scan::SizeMode::Disk;
+    } else if cli.inodes {
+        app.size_mode = scan::SizeMode::Inodes;
+    }
+    let cache_entries = cli.cache_entries.or(config.cache_entries);
+    app.size_cache
+        .set_max_entries(cache_entries.unwrap_or(cache::DEFAULT_MAX_ENTRIES));
+    app.undo_grace_hours = undo_grace_hours;
+    if cli.watch {
+        app.toggle_watch();
+    }
+    let result = run_app(&mut terminal, &mut app);
 
     // Cleanup terminal - always restore state even on error
     let _ = terminal.show_cursor();
@@ -46,12 +148,52 @@ fn main() -> Result<(), Box<dyn Error>> {
         eprintln!("Error: {}", e);
     }
 
+    if let Err(e) = app.save_cache() {
+        eprintln!("Warning: could not save size cache: {}", e);
+    }
+
+    Ok(())
+}
+
+/// `--undo N`: restore the most recent transactions, newest first
+fn undo_latest(count: usize) -> Result<(), Box<dyn Error>> {
+    let undo = undo::undo(count)?;
+    for (transaction, error) in &undo.skipped {
+        eprintln!(
+            "Skipped {}: {}",
+            transaction.original_path().display(),
+            error
+        );
+    }
+    for transaction in &undo.restored {
+        println!("Restored {}", transaction.original_path().display());
+    }
+    if undo.restored.len() < count {
+        println!("Nothing left to undo");
+    }
+    Ok(())
+}
+
+/// `--purge-staged`: delete everything in the staging area for good
+fn purge_staged() -> Result<(), Box<dyn Error>> {
+    let purged = undo::purge(None)?;
+    let mut freed = 0;
+    for (transaction, result) in &purged {
+        freed += result.total_bytes;
+        for error in &result.errors {
+            eprintln!("{}: {}", transaction.original_path().display(), error);
+        }
+    }
+    println!(
+        "Purged {} staged deletions ({:.1} MB freed)",
+        purged.len(),
+        freed as f64 / 1_000_000.0
+    );
     Ok(())
 }
 
-fn resolve_start_path() -> Result<Option<PathBuf>, Box<dyn Error>> {
-    if let Some(arg) = std::env::args().nth(1) {
-        let path = PathBuf::from(arg);
+fn resolve_start_path(path: Option<PathBuf>) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if let Some(path) = path {
         if !path.exists() {
             return Err(format!("Path does not exist: {}", path.display()).into());
         }
@@ -64,12 +206,12 @@ fn resolve_start_path() -> Result<Option<PathBuf>, Box<dyn Error>> {
     }
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), Box<dyn Error>> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(), Box<dyn Error>> {
     loop {
-        let viewport_height = terminal.size()?.height as usize;
+        let viewport_height = ui::browser_height(terminal.size()?.height);
 
         terminal.draw(|f| {
-            ui::draw(f, &app);
+            ui::draw(f, app);
         })?;
 
         // Adjust scroll to keep selected item visible
@@ -77,7 +219,7 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), B
 
         if crossterm::event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if handle_input(&mut app, key)? {
+                if handle_input(app, key)? {
                     break;
                 }
             }
@@ -89,6 +231,12 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), B
         // Update delete progress if thread is running
         app.update_delete_progress();
 
+        // Apply filesystem changes in watch mode
+        app.update_watch();
+
+        // Unlink staged deletes once their grace period is over
+        app.update_purge();
+
         // Clear notification after 3 seconds
         if let Some(notif_time) = app.notification_time {
             if notif_time.elapsed().as_secs() > 3 {
@@ -108,6 +256,22 @@ fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
         return Ok(false);
     }
 
+    // The history view closes on any key
+    if app.show_history {
+        app.show_history = false;
+        return Ok(false);
+    }
+
+    // The errors panel scrolls with j/k, any other key closes it
+    if app.show_errors {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => app.scroll_errors(false),
+            KeyCode::Down | KeyCode::Char('j') => app.scroll_errors(true),
+            _ => app.show_errors = false,
+        }
+        return Ok(false);
+    }
+
     // If modal is open, handle modal input
     if app.modal.is_some() {
         return handle_modal_input(app, key);
@@ -117,9 +281,13 @@ fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
     match key.code {
         KeyCode::Char('q') => return Ok(true), // 'q' to quit
         KeyCode::Esc => {
-            // Esc key closes modals if open, otherwise quits
+            // Esc key closes modals if open, cancels a running deletion or scan, otherwise quits
             if app.modal.is_some() {
                 app.modal = None;
+            } else if app.delete_progress.is_some() {
+                app.cancel_delete();
+            } else if app.is_scanning {
+                app.cancel_scan();
             } else {
                 return Ok(true);
             }
@@ -132,6 +300,14 @@ fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
         KeyCode::Char('?') => app.toggle_help(),
         KeyCode::Char('r') => app.refresh(),
         KeyCode::Char('c') => app.hard_refresh(), // 'c' to clear cache and refresh
+        KeyCode::Char('a') => app.toggle_size_mode(), // apparent size, disk usage, inodes
+        KeyCode::Char('s') => app.toggle_sort_mode(), // size vs item count
+        KeyCode::Char('e') => app.toggle_errors(),
+        KeyCode::Char('w') => app.toggle_watch(), // live updates via inotify
+        KeyCode::Char('b') => app.cycle_baseline(), // diff against an older scan
+        KeyCode::Char('t') => app.toggle_history(), // size timeline of the selection
+        KeyCode::Char('g') => app.toggle_removed(), // ghost rows for removed entries
+        KeyCode::Char('u') => app.undo_last(),    // restore the last deletion
         _ => {}
     }
 
@@ -141,15 +317,13 @@ fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
 fn handle_modal_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
     if let Some(modal) = &mut app.modal {
         match key.code {
-            KeyCode::Left | KeyCode::Char('h') => {
-                if modal.selected_button > 0 {
-                    modal.selected_button -= 1;
-                }
+            KeyCode::Left | KeyCode::Char('h') if modal.selected_button > 0 => {
+                modal.selected_button -= 1;
             }
-            KeyCode::Right | KeyCode::Char('l') => {
-                if modal.selected_button < modal.buttons.len() - 1 {
-                    modal.selected_button += 1;
-                }
+            KeyCode::Right | KeyCode::Char('l')
+                if modal.selected_button < modal.buttons.len() - 1 =>
+            {
+                modal.selected_button += 1;
             }
             KeyCode::Tab => {
                 modal.selected_button = (modal.selected_button + 1) % modal.buttons.len();
@@ -168,9 +342,12 @@ fn handle_modal_input(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Erro
             KeyCode::Esc => {
                 app.modal = None;
             }
-            KeyCode::Char('y') if modal.has_button("Yes") => {
+            KeyCode::Char('y') | KeyCode::Char('t') if modal.has_button("Trash") => {
                 return handle_modal_action(app, modal::ModalAction::Confirm);
             }
+            KeyCode::Char('p') if modal.has_button("Delete permanently") => {
+                return handle_modal_action(app, modal::ModalAction::DeletePermanently);
+            }
             KeyCode::Char('n') if modal.has_button("No") => {
                 return handle_modal_action(app, modal::ModalAction::Cancel);
             }
@@ -189,23 +366,36 @@ fn handle_modal_action(app: &mut App, action: modal::ModalAction) -> Result<bool
         modal::ModalAction::Confirm => {
             if let Some(modal) = app.modal.take() {
                 match modal.modal_type {
-                    modal::ModalType::ConfirmDelete { path, size } => {
-                        // Move to final confirmation
-                        app.modal = Some(modal::Modal::final_confirm(&path, size));
+                    modal::ModalType::ConfirmDelete { path, totals, .. } => {
+                        // The trash can be restored, no second confirmation needed
+                        app.start_delete(&path, totals, delete::DeleteMethod::Trash)?;
                     }
-                    modal::ModalType::FinalConfirm { path, size: _ } => {
+                    modal::ModalType::FinalConfirm { path, totals, .. } => {
                         // Start deletion
                         app.modal = None;
-                        app.start_delete(&path)?;
+                        app.start_delete(&path, totals, delete::DeleteMethod::Permanent)?;
                     }
                     #[allow(unreachable_patterns)]
                     _ => {}
                 }
             }
         }
+        modal::ModalAction::DeletePermanently => {
+            if let Some(modal) = app.modal.take() {
+                if let modal::ModalType::ConfirmDelete { path, size, totals } = modal.modal_type {
+                    // Move to final confirmation
+                    app.modal = Some(modal::Modal::final_confirm(
+                        &path,
+                        size,
+                        app.undo_grace_hours,
+                        totals,
+                    ));
+                }
+            }
+        }
         modal::ModalAction::DryRun => {
             if let Some(modal) = app.modal.take() {
-                if let modal::ModalType::ConfirmDelete { path, size: _ } = modal.modal_type {
+                if let modal::ModalType::ConfirmDelete { path, .. } = modal.modal_type {
                     app.start_dry_run(&path)?;
                 }
             }
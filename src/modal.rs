@@ -1,14 +1,27 @@
-use std::path::PathBuf;
+use crate::delete::DeleteTotals;
+use crate::scan::display_name;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug)]
 pub enum ModalType {
-    ConfirmDelete { path: PathBuf, size: u64 },
-    FinalConfirm { path: PathBuf, size: u64 },
+    ConfirmDelete {
+        path: PathBuf,
+        size: u64,
+        totals: DeleteTotals,
+    },
+    /// `undo_hours` is the undo grace period, 0 if the delete is final
+    FinalConfirm {
+        path: PathBuf,
+        size: u64,
+        undo_hours: u64,
+        totals: DeleteTotals,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ModalAction {
     Confirm,
+    DeletePermanently,
     DryRun,
     Cancel,
 }
@@ -20,26 +33,33 @@ pub struct Modal {
 }
 
 impl Modal {
-    pub fn confirm_delete(path: &PathBuf, size: u64) -> Self {
+    pub fn confirm_delete(path: &Path, size: u64, totals: DeleteTotals) -> Self {
         Modal {
             modal_type: ModalType::ConfirmDelete {
-                path: path.clone(),
+                path: path.to_path_buf(),
                 size,
+                totals,
             },
             selected_button: 0,
             buttons: vec![
-                ("Yes".to_string(), ModalAction::Confirm),
+                ("Trash".to_string(), ModalAction::Confirm),
+                (
+                    "Delete permanently".to_string(),
+                    ModalAction::DeletePermanently,
+                ),
                 ("No".to_string(), ModalAction::Cancel),
                 ("Dry-run".to_string(), ModalAction::DryRun),
             ],
         }
     }
 
-    pub fn final_confirm(path: &PathBuf, size: u64) -> Self {
+    pub fn final_confirm(path: &Path, size: u64, undo_hours: u64, totals: DeleteTotals) -> Self {
         Modal {
             modal_type: ModalType::FinalConfirm {
-                path: path.clone(),
+                path: path.to_path_buf(),
                 size,
+                undo_hours,
+                totals,
             },
             selected_button: 1, // Default to Cancel for safety
             buttons: vec![
@@ -55,17 +75,17 @@ impl Modal {
 
     pub fn get_title(&self) -> String {
         match &self.modal_type {
-            ModalType::ConfirmDelete { path, size } => {
+            ModalType::ConfirmDelete { path, size, .. } => {
                 format!(
                     "Delete {} ({})? ",
-                    path.file_name().and_then(|n| n.to_str()).unwrap_or("?"),
+                    path.file_name().map_or("?".to_string(), display_name),
                     format_size(*size)
                 )
             }
-            ModalType::FinalConfirm { path, size } => {
+            ModalType::FinalConfirm { path, size, .. } => {
                 format!(
-                    "FINAL CONFIRMATION - Delete {} ({})? ",
-                    path.file_name().and_then(|n| n.to_str()).unwrap_or("?"),
+                    "FINAL CONFIRMATION - Permanently delete {} ({})? ",
+                    path.file_name().map_or("?".to_string(), display_name),
                     format_size(*size)
                 )
             }
@@ -74,10 +94,17 @@ impl Modal {
 
     pub fn get_message(&self) -> String {
         match &self.modal_type {
-            ModalType::ConfirmDelete { .. } => "This cannot be undone!".to_string(),
-            ModalType::FinalConfirm { .. } => {
-                "Really confirm? This is your last chance!".to_string()
+            ModalType::ConfirmDelete { .. } => {
+                "Trash can be restored from the file manager, [u] undoes the last deletion"
+                    .to_string()
             }
+            ModalType::FinalConfirm { undo_hours: 0, .. } => {
+                "Really confirm? This cannot be undone!".to_string()
+            }
+            ModalType::FinalConfirm { undo_hours, .. } => format!(
+                "Really confirm? [u] can undo it for {}h, disk space is only freed after that",
+                undo_hours
+            ),
         }
     }
 }
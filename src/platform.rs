@@ -6,11 +6,15 @@ pub struct DiskSpace {
     pub total_bytes: u64,
     pub available_bytes: u64,
     pub used_bytes: u64,
+    /// Inode totals, 0 on filesystems without a fixed inode table (e.g. btrfs)
+    pub total_inodes: u64,
+    pub used_inodes: u64,
 }
 
 /// Get disk space information for the filesystem containing the given path
 /// Works on both macOS (APFS, HFS+, etc.) and Linux (ext4, btrfs, xfs, etc.)
 #[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field widths differ between Linux and macOS
 pub fn get_disk_space(path: &Path) -> Option<DiskSpace> {
     use nix::sys::statvfs::statvfs;
 
@@ -27,10 +31,15 @@ pub fn get_disk_space(path: &Path) -> Option<DiskSpace> {
             let available_bytes = available_blocks * fragment_size;
             let used_bytes = total_bytes.saturating_sub(available_bytes);
 
+            let total_inodes = stat.files() as u64;
+            let used_inodes = total_inodes.saturating_sub(stat.files_free() as u64);
+
             Some(DiskSpace {
                 total_bytes,
                 available_bytes,
                 used_bytes,
+                total_inodes,
+                used_inodes,
             })
         }
         Err(_) => None,
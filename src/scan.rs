@@ -1,104 +1,1083 @@
-use crate::cache::SizeCache;
+use crate::cache::{file_signature, SizeCache};
+use crate::exclude::{ExcludeRules, IgnoreStack, IGNORE_FILE};
+use crate::tree::{Node, Totals};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::mpsc;
-use walkdir::WalkDir;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
 
 #[derive(Clone, Debug)]
 pub struct DirEntry {
     pub path: PathBuf,
-    pub name: String,
+    pub name: OsString, // Raw file name, may not be valid UTF-8
     pub size: u64,
+    pub disk_size: u64,
+    pub shared_size: u64, // Bytes of hard links whose inode was counted elsewhere
     pub is_dir: bool,
-    #[allow(dead_code)]
-    pub file_count: u64,
+    pub file_count: u64, // Recursive counts, see tree::Node
+    pub dir_count: u64,
+    pub symlink_count: u64,
+    pub partial: bool,  // Size is a lower bound, the scan stopped at the file limit
+    pub other_fs: bool, // Mount point skipped by --one-file-system
+    pub excluded: bool, // Matched an exclude pattern, never measured
+    pub error_count: u64, // Unreadable paths in this subtree
+    pub mtime: u64,     // Seconds since the epoch, 0 if unknown
     pub size_change: Option<(i64, f32)>, // (delta_bytes, percent_of_directory)
-    #[allow(dead_code)]
-    pub is_new: bool, // True if this didn't exist before
+    pub is_new: bool,   // True if this didn't exist before
+    pub removed: bool,  // Gone since the compared scan, a ghost row with the old size
 }
 
-pub fn scan_directory(
-    path: &PathBuf,
+impl DirEntry {
+    /// Size used for sorting, bars and percentages in the given mode
+    /// (an entry count rather than bytes in inode mode)
+    pub fn size_for(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Disk => self.disk_size,
+            SizeMode::Inodes => self.item_count(),
+        }
+    }
+
+    /// Files, directories and symlinks below a directory (1 for a file)
+    pub fn item_count(&self) -> u64 {
+        self.file_count + self.dir_count + self.symlink_count
+    }
+
+    pub fn display_name(&self) -> String {
+        display_name(&self.name)
+    }
+}
+
+/// Printable form of a file name: invalid UTF-8 bytes are shown as `\xNN`
+/// and control characters are escaped, so odd names can't corrupt the screen
+pub fn display_name(name: &OsStr) -> String {
+    let mut out = String::new();
+    for chunk in name.as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                out.extend(c.escape_default());
+            } else {
+                out.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    out
+}
+
+/// File name from raw bytes, as stored in the fingerprint and cache files
+#[cfg(unix)]
+pub fn bytes_to_os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// Which size drives the browser display
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SizeMode {
+    /// File length as reported by stat (st_size)
+    #[default]
+    Apparent,
+    /// Space allocated on disk (st_blocks * 512)
+    Disk,
+    /// Recursive entry count, for hunting inode exhaustion
+    Inodes,
+}
+
+impl SizeMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            SizeMode::Apparent => SizeMode::Disk,
+            SizeMode::Disk => SizeMode::Inodes,
+            SizeMode::Inodes => SizeMode::Apparent,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SizeMode::Apparent => "apparent",
+            SizeMode::Disk => "disk usage",
+            SizeMode::Inodes => "inodes",
+        }
+    }
+
+    /// Whether `DirEntry::size_for` is in bytes
+    pub fn is_bytes(self) -> bool {
+        self != SizeMode::Inodes
+    }
+}
+
+/// What the browser is ordered and coloured by
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortMode {
+    #[default]
+    Size,
+    /// Recursive number of files, directories and symlinks
+    Items,
+}
+
+impl SortMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            SortMode::Size => SortMode::Items,
+            SortMode::Items => SortMode::Size,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Size => "by size",
+            SortMode::Items => "by items",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// Number of scanner threads, 0 means one per CPU
+    pub threads: usize,
+    /// Stop descending into new directories after this many files.
+    /// `None` scans everything and gives exact sizes.
+    pub max_files: Option<u64>,
+    /// Don't cross into other filesystems (like `du -x`)
+    pub one_filesystem: bool,
+    /// Paths to skip, in addition to `.mcduignore` files
+    pub exclude: ExcludeRules,
+    /// List excluded entries (without a size) instead of hiding them
+    pub show_excluded: bool,
+}
+
+impl ScanOptions {
+    /// Settings that change the measured totals, cached totals are only
+    /// reused by sessions with the same settings
+    pub fn cache_settings(&self) -> String {
+        format!(
+            "one_filesystem={} {}",
+            self.one_filesystem,
+            self.exclude.describe()
+        )
+    }
+}
+
+/// A path that could not be read during a scan
+#[derive(Clone, Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    /// Short errno name like EACCES, ELOOP or EIO
+    pub kind: String,
+    pub message: String,
+}
+
+impl ScanError {
+    fn new(path: &Path, error: &io::Error) -> Self {
+        let kind = match error.raw_os_error() {
+            Some(code) => format!("{:?}", nix::errno::Errno::from_raw(code)),
+            None => format!("{:?}", error.kind()),
+        };
+        ScanError {
+            path: path.to_path_buf(),
+            kind,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Result of a successful scan: the tree plus every path that could not be read
+pub struct ScanOutput {
+    pub node: Node,
+    pub errors: Vec<ScanError>,
+}
+
+/// State shared by all threads during one scan
+struct Walker<'a> {
+    cache: &'a SizeCache,
+    options: &'a ScanOptions,
+    files_seen: AtomicU64,
+    // (dev, ino) of links whose bytes are already counted outside this scan
+    counted_links: HashSet<(u64, u64)>,
+    // Lexicographically smallest path seen for each other multiply-linked
+    // inode; that link gets the bytes once the walk is done
+    link_owners: Mutex<HashMap<(u64, u64), PathBuf>>,
+    // Device of the scan root, set when staying on one filesystem
+    root_dev: Option<u64>,
+    // Set by the UI when this scan is no longer wanted
+    cancel: &'a AtomicBool,
+    errors: Mutex<Vec<ScanError>>,
+}
+
+/// Recursively scan `path` on a rayon pool (the caller's, if called from one).
+/// Still-valid cached subtrees are reused from `previous`, and hard links in
+/// `counted_links` only add to the shared size. Unreadable paths below `path`
+/// end up in `ScanOutput::errors`; after `cancel` is set the tree is incomplete.
+pub fn scan_tree(
+    path: &Path,
     cache: &SizeCache,
+    previous: Option<&Node>,
+    counted_links: HashSet<(u64, u64)>,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
     progress_tx: Option<&mpsc::Sender<crate::app::ScanResult>>,
-) -> Result<Vec<DirEntry>, Box<dyn std::error::Error>> {
-    // Scan immediate children (non-recursive)
-    let children: Vec<_> = fs::read_dir(path)?.filter_map(|e| e.ok()).collect();
+) -> Result<ScanOutput, Box<dyn std::error::Error>> {
+    let read_dir = fs::read_dir(path)?;
 
+    let root_metadata = fs::metadata(path).ok();
+    let walker = Walker {
+        cache,
+        options,
+        files_seen: AtomicU64::new(0),
+        counted_links,
+        link_owners: Mutex::new(HashMap::new()),
+        root_dev: root_metadata
+            .as_ref()
+            .filter(|_| options.one_filesystem)
+            .map(device_id),
+        cancel,
+        errors: Mutex::new(Vec::new()),
+    };
+
+    let (children, listing_errors) = walker.list(path, read_dir);
     let total_count = children.len();
-    let mut scanned_count = 0;
+    let scanned_count = AtomicUsize::new(0);
 
-    // Process sequentially - directory size calculation is I/O bound and parallel doesn't help much
-    // Plus we need to keep responsiveness
-    let entries: Vec<DirEntry> = children
-        .into_iter()
-        .filter_map(|entry| {
-            let path = entry.path();
-            let metadata = entry.metadata().ok()?;
-            let is_dir = metadata.is_dir();
-
-            let name = path.file_name()?.to_str()?.to_string();
-
-            // Fast size calculation - reuse metadata for files, use cache for dirs
-            let size = if is_dir {
-                // Send progress update for directories (skip files since they're fast)
-                if let Some(tx) = progress_tx {
-                    scanned_count += 1;
-                    let _ = tx.send(crate::app::ScanResult::Progress {
-                        current_name: name.clone(),
-                        scanned_count,
-                        total_count,
-                    });
+    let ignores = IgnoreStack::for_root(path);
+    let walk = || -> Vec<(Option<Node>, u64)> {
+        children
+            .par_iter()
+            .map(|entry| {
+                // Send progress updates for directories (skip files since they're fast)
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    if let Some(tx) = progress_tx {
+                        let _ = tx.send(crate::app::ScanResult::Progress {
+                            current_name: display_name(&entry.file_name()),
+                            scanned_count: scanned_count.fetch_add(1, Ordering::Relaxed) + 1,
+                            total_count,
+                        });
+                    }
                 }
-                // Try cache first, fall back to scanning
-                if let Some(cached_size) = cache.get(&path) {
-                    cached_size
-                } else {
-                    let size = quick_dir_size(&path);
-                    cache.set(path.clone(), size);
-                    size
+
+                walker.child_node(entry, previous, &ignores)
+            })
+            .collect()
+    };
+    let scanned = if rayon::current_thread_index().is_some() {
+        walk()
+    } else {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .build()?
+            .install(walk)
+    };
+    let (nodes, files) = collect_children(scanned);
+
+    let root_name = path.as_os_str().to_os_string();
+    let mut node = walker.dir_node(
+        root_name,
+        root_metadata.as_ref(),
+        nodes,
+        files,
+        listing_errors,
+    );
+    node.mtime = root_metadata.as_ref().map_or(0, modified_secs);
+
+    // Which link holds an inode's bytes must not depend on thread scheduling
+    let owners = walker.link_owners.into_inner().unwrap();
+    if !owners.is_empty() {
+        settle_links(&mut node, path, &owners);
+    }
+
+    let mut errors = walker.errors.into_inner().unwrap();
+    errors.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ScanOutput { node, errors })
+}
+
+impl Walker<'_> {
+    fn record_error(&self, path: &Path, error: &io::Error) {
+        self.errors
+            .lock()
+            .unwrap()
+            .push(ScanError::new(path, error));
+    }
+
+    /// Collect the entries of a directory listing, recording entries that
+    /// could not be read. Returns the entries and the number of failures.
+    fn list(&self, path: &Path, read_dir: fs::ReadDir) -> (Vec<fs::DirEntry>, u64) {
+        let mut entries = Vec::new();
+        let mut failures = 0;
+        for entry in read_dir {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    self.record_error(path, &e);
+                    failures += 1;
                 }
-            } else {
-                metadata.len()
+            }
+        }
+        (entries, failures)
+    }
+
+    /// Scan one directory entry. Returns None if it is excluded and
+    /// excluded entries are hidden, along with the entry's part of the
+    /// directory's cache signature (`cache::file_signature`, 0 for directories).
+    fn child_node(
+        &self,
+        entry: &fs::DirEntry,
+        previous: Option<&Node>,
+        ignores: &IgnoreStack,
+    ) -> (Option<Node>, u64) {
+        let path = entry.path();
+        let name = entry.file_name();
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+
+        if self.options.exclude.is_excluded(&path)
+            || ignores.is_ignored(&path)
+            || crate::undo::is_staging_dir(&path)
+        {
+            // Signed like any other entry, the cache doesn't know the excludes
+            let files = match entry.metadata() {
+                Ok(metadata) if !is_dir => file_signature(&name, &metadata),
+                _ => 0,
             };
+            if !self.options.show_excluded {
+                return (None, files);
+            }
+            let mut node = Node::dir(name, Vec::new());
+            node.is_dir = is_dir;
+            node.excluded = true;
+            return (Some(node), files);
+        }
 
-            Some(DirEntry {
-                path,
-                name,
-                size,
-                is_dir,
-                file_count: 0,
-                size_change: None,
-                is_new: false,
-            })
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                // Keep unreadable entries visible so they can be flagged
+                self.record_error(&path, &e);
+                let mut node = Node::dir(name, Vec::new());
+                node.is_dir = is_dir;
+                node.error_count = 1;
+                return (Some(node), 0);
+            }
+        };
+        let files = if metadata.is_dir() {
+            0
+        } else {
+            file_signature(&name, &metadata)
+        };
+        let previous_child = previous.and_then(|p| p.child(&name));
+        let mut node = self.scan_node(&path, name, &metadata, previous_child, ignores);
+        node.mtime = modified_secs(&metadata);
+        (Some(node), files)
+    }
+
+    fn scan_node(
+        &self,
+        path: &Path,
+        name: OsString,
+        metadata: &fs::Metadata,
+        previous: Option<&Node>,
+        ignores: &IgnoreStack,
+    ) -> Node {
+        if !metadata.is_dir() {
+            self.files_seen.fetch_add(1, Ordering::Relaxed);
+
+            if metadata.file_type().is_symlink() {
+                let mut node = Node::file(name, metadata.len(), allocated_size(metadata));
+                node.file_count = 0;
+                node.symlink_count = 1;
+                return node;
+            }
+
+            // Links to an inode counted outside this scan add no bytes. The
+            // others are counted for now and settled by `settle_links`.
+            let hardlink = hardlink_key(metadata);
+            let mut node = match hardlink {
+                Some(key) if self.counted_links.contains(&key) => {
+                    let mut node = Node::file(name, 0, 0);
+                    node.shared_size = metadata.len();
+                    node
+                }
+                Some(key) => {
+                    let mut owners = self.link_owners.lock().unwrap();
+                    let owner = owners.entry(key).or_insert_with(|| path.to_path_buf());
+                    if path < owner.as_path() {
+                        *owner = path.to_path_buf();
+                    }
+                    Node::file(name, metadata.len(), allocated_size(metadata))
+                }
+                None => Node::file(name, metadata.len(), allocated_size(metadata)),
+            };
+            node.hardlink = hardlink;
+            return node;
+        }
+
+        // Mount point of another filesystem: keep it visible but don't descend
+        if self.root_dev.is_some_and(|dev| dev != device_id(metadata)) {
+            let mut node = Node::dir(name, Vec::new());
+            node.other_fs = true;
+            return node;
+        }
+
+        // Unmodified since its totals were cached: reuse the previous subtree if it
+        // matches, otherwise stand in with the cached totals until it is opened.
+        // Subtrees with errors are never cached, so their errors are reported again.
+        if let Some(totals) = self.cache.get(path, metadata) {
+            return match previous.filter(|p| p.is_dir && !p.partial && p.error_count == 0) {
+                Some(previous) if Totals::of(previous) == totals => previous.clone(),
+                _ => Node::cached_dir(name, totals),
+            };
+        }
+
+        // Over the file budget or cancelled: keep the directory but don't descend into it
+        if self.over_limit() || self.cancel.load(Ordering::Relaxed) {
+            let mut node = Node::dir(name, Vec::new());
+            node.partial = true;
+            return node;
+        }
+
+        let (children, files, failures) = match fs::read_dir(path) {
+            Ok(read_dir) => {
+                let (entries, failures) = self.list(path, read_dir);
+
+                // Only pay for reading an ignore file where one exists
+                let ignores = if entries.iter().any(|e| e.file_name() == IGNORE_FILE) {
+                    ignores.enter(path)
+                } else {
+                    ignores.clone()
+                };
+
+                let (children, files) = collect_children(
+                    entries
+                        .into_par_iter()
+                        .map(|entry| self.child_node(&entry, previous, &ignores))
+                        .collect(),
+                );
+                (children, files, failures)
+            }
+            Err(e) => {
+                self.record_error(path, &e);
+                (Vec::new(), 0, 1)
+            }
+        };
+
+        self.dir_node(name, Some(metadata), children, files, failures)
+    }
+
+    fn over_limit(&self) -> bool {
+        self.options
+            .max_files
+            .is_some_and(|max| self.files_seen.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Build a directory node from its scanned children and record it in the cache.
+    /// `files` is the signature of its non-directory entries, `failures` counts
+    /// entries of this directory that could not be listed.
+    /// Truncated or incomplete sizes are never cached, nor directories holding
+    /// hard links: which link counts depends on the rest of the scan.
+    fn dir_node(
+        &self,
+        name: OsString,
+        metadata: Option<&fs::Metadata>,
+        children: Vec<Node>,
+        files: u64,
+        failures: u64,
+    ) -> Node {
+        let mut node = Node::dir(name, children);
+        // The directory itself occupies blocks too (like du)
+        node.disk_size += metadata.map_or(0, allocated_size);
+        node.error_count += failures;
+        let cacheable = !node.partial
+            && node.error_count == 0
+            && node.children.iter().all(|c| c.hardlink.is_none());
+        if let Some(metadata) = metadata.filter(|_| cacheable) {
+            // Cache what this directory holds itself, subdirectories by reference
+            let mut own = Totals {
+                disk_size: allocated_size(metadata),
+                ..Default::default()
+            };
+            let mut subdirs = Vec::new();
+            for child in &node.children {
+                if !child.is_dir {
+                    own.add(&Totals::of(child));
+                } else if child.other_fs {
+                    own.dir_count += 1;
+                } else if !child.excluded {
+                    subdirs.push(child.name.clone());
+                }
+            }
+            self.cache.set(metadata, own, subdirs, files);
+        }
+        node
+    }
+}
+
+/// Split scanned entries into their nodes and the sum of their signatures
+fn collect_children(scanned: Vec<(Option<Node>, u64)>) -> (Vec<Node>, u64) {
+    let mut files = 0u64;
+    let nodes = scanned
+        .into_iter()
+        .filter_map(|(node, signature)| {
+            files = files.wrapping_add(signature);
+            node
         })
         .collect();
+    (nodes, files)
+}
+
+/// Bytes actually allocated on disk, which differs from the apparent
+/// size for sparse files and for files smaller than a block
+#[cfg(unix)]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
 
-    // Sort by size, largest first
-    let mut sorted = entries;
-    sorted.sort_by(|a, b| b.size.cmp(&a.size));
+#[cfg(not(unix))]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
 
-    Ok(sorted)
+/// Modification time in whole seconds since the epoch, 0 if unavailable
+fn modified_secs(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
 }
 
-fn quick_dir_size(path: &std::path::Path) -> u64 {
-    // Optimized size calculation with early termination for huge directories
-    // Uses DirEntry's metadata when available to avoid double stat calls
-    let mut total = 0u64;
-    const MAX_FILES: usize = 100_000; // Increased from 50k for better accuracy
+#[cfg(unix)]
+fn device_id(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
 
-    for entry in WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .take(MAX_FILES)
-    {
-        // Get file size from metadata already loaded by WalkDir
-        if let Ok(metadata) = entry.metadata() {
-            if metadata.is_file() {
-                total += metadata.len();
+#[cfg(not(unix))]
+fn device_id(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+/// Identity of a file that has other hard links pointing at it
+#[cfg(unix)]
+fn hardlink_key(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn hardlink_key(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Move the bytes of every hard link other than its inode's owner into the
+/// shared size, fixing up directory totals on the way back up.
+/// Returns the (size, disk size) moved out of `node`.
+fn settle_links(node: &mut Node, path: &Path, owners: &HashMap<(u64, u64), PathBuf>) -> (u64, u64) {
+    if let Some(key) = node.hardlink {
+        if node.shared_size > 0 || owners.get(&key).is_none_or(|owner| owner == path) {
+            return (0, 0);
+        }
+        let moved = (node.size, node.disk_size);
+        node.shared_size = node.size;
+        node.size = 0;
+        node.disk_size = 0;
+        return moved;
+    }
+
+    let (mut size, mut disk_size) = (0, 0);
+    for child in &mut node.children {
+        if child.hardlink.is_none() && child.children.is_empty() {
+            continue;
+        }
+        let child_path = path.join(&child.name);
+        let moved = settle_links(child, &child_path, owners);
+        size += moved.0;
+        disk_size += moved.1;
+    }
+    node.size -= size;
+    node.disk_size -= disk_size;
+    node.shared_size += size;
+    (size, disk_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(root: &Path, options: &ScanOptions) -> Node {
+        scan_tree(
+            root,
+            &SizeCache::new(),
+            None,
+            HashSet::new(),
+            options,
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap()
+        .node
+    }
+
+    #[test]
+    fn scan_tree_sums_nested_sizes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("top.txt"), vec![0u8; 10]).unwrap();
+        fs::write(root.join("a/one.txt"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("a/b/two.txt"), vec![0u8; 1000]).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+
+        assert_eq!(node.size, 1110);
+        assert_eq!(node.file_count, 3);
+        let a = node.child("a").unwrap();
+        assert_eq!(a.size, 1100);
+        assert_eq!(a.child("b").unwrap().size, 1000);
+    }
+
+    #[test]
+    fn scan_tree_is_stable_across_thread_counts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for i in 0..8 {
+            let dir = root.join(format!("d{}", i));
+            fs::create_dir_all(dir.join("nested")).unwrap();
+            fs::write(dir.join("nested/f"), vec![0u8; 10 * (i + 1)]).unwrap();
+        }
+
+        let single = ScanOptions {
+            threads: 1,
+            ..Default::default()
+        };
+        let many = ScanOptions {
+            threads: 4,
+            ..Default::default()
+        };
+        let a = scan(&root, &single);
+        let b = scan(&root, &many);
+
+        assert_eq!(a.size, 360);
+        assert_eq!(a.size, b.size);
+        assert_eq!(a.file_count, b.file_count);
+    }
+
+    #[test]
+    fn file_limit_marks_tree_partial() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for i in 0..4 {
+            let dir = root.join(format!("d{}", i));
+            fs::create_dir_all(&dir).unwrap();
+            for j in 0..5 {
+                fs::write(dir.join(format!("f{}", j)), b"x").unwrap();
+            }
+        }
+
+        let exact = scan(&root, &ScanOptions::default());
+        assert_eq!(exact.file_count, 20);
+        assert!(!exact.partial);
+
+        let limited = ScanOptions {
+            threads: 1,
+            max_files: Some(5),
+            ..Default::default()
+        };
+        let cache = SizeCache::new();
+        let node = scan_tree(
+            &root,
+            &cache,
+            None,
+            HashSet::new(),
+            &limited,
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap()
+        .node;
+        assert!(node.partial);
+        assert!(node.file_count < 20);
+        assert!(node.children.iter().any(|c| c.partial));
+        // Truncated sizes must not end up in the cache
+        assert_eq!(cache.get(&root, &fs::metadata(&root).unwrap()), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sparse_file_has_small_disk_size() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let file = fs::File::create(root.join("sparse.img")).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+        drop(file);
+
+        let node = scan(&root, &ScanOptions::default());
+        let sparse = node.child("sparse.img").unwrap();
+        assert_eq!(sparse.size, 64 * 1024 * 1024);
+        assert!(sparse.disk_size < sparse.size);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_counted_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("daily.0")).unwrap();
+        fs::create_dir_all(root.join("daily.1")).unwrap();
+        fs::write(root.join("daily.0/data"), vec![0u8; 5000]).unwrap();
+        fs::hard_link(root.join("daily.0/data"), root.join("daily.1/data")).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+
+        assert_eq!(node.size, 5000);
+        assert_eq!(node.shared_size, 5000);
+        let counted: u64 = node.children.iter().map(|c| c.size).sum();
+        let shared: u64 = node.children.iter().map(|c| c.shared_size).sum();
+        assert_eq!((counted, shared), (5000, 5000));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_link_bytes_go_to_the_smallest_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for name in ["daily.0", "daily.1", "daily.2", "daily.3"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+        }
+        fs::write(root.join("daily.2/data"), vec![0u8; 5000]).unwrap();
+        for name in ["daily.0", "daily.1", "daily.3"] {
+            fs::hard_link(root.join("daily.2/data"), root.join(name).join("data")).unwrap();
+        }
+
+        let options = ScanOptions {
+            threads: 4,
+            ..ScanOptions::default()
+        };
+        for _ in 0..20 {
+            let node = scan(&root, &options);
+            assert_eq!(node.size, 5000);
+            assert_eq!(node.shared_size, 15000);
+            let owner = node.child("daily.0").unwrap();
+            assert_eq!((owner.size, owner.shared_size), (5000, 0));
+            for name in ["daily.1", "daily.2", "daily.3"] {
+                let link = node.child(name).unwrap();
+                assert_eq!((link.size, link.shared_size), (0, 5000));
+                assert_eq!(link.child("data").unwrap().disk_size, 0);
             }
         }
     }
 
-    total
+    #[cfg(unix)]
+    #[test]
+    fn rescanning_a_subtree_keeps_hard_links_counted_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("daily.0")).unwrap();
+        fs::create_dir_all(root.join("daily.1")).unwrap();
+        fs::write(root.join("daily.0/data"), vec![0u8; 5000]).unwrap();
+        fs::hard_link(root.join("daily.0/data"), root.join("daily.1/data")).unwrap();
+
+        let cache = SizeCache::new();
+        let never = AtomicBool::new(false);
+        let options = ScanOptions::default();
+        let mut tree = crate::tree::DirTree::new(root.clone(), scan(&root, &options));
+        assert_eq!(tree.root.size, 5000);
+
+        for name in ["daily.0", "daily.1"] {
+            let path = root.join(name);
+            let output = scan_tree(
+                &path,
+                &cache,
+                tree.find(&path),
+                tree.counted_links_outside(&path),
+                &options,
+                &never,
+                None,
+            )
+            .unwrap();
+            assert!(tree.replace(&path, output.node));
+            assert_eq!(tree.root.size, 5000);
+            assert_eq!(tree.root.shared_size, 5000);
+        }
+    }
+
+    #[test]
+    fn one_filesystem_descends_into_same_device() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/f"), vec![0u8; 42]).unwrap();
+
+        let options = ScanOptions {
+            one_filesystem: true,
+            ..Default::default()
+        };
+        let node = scan(&root, &options);
+
+        let a = node.child("a").unwrap();
+        assert!(!a.other_fs);
+        assert_eq!(a.size, 42);
+    }
+
+    #[test]
+    fn excluded_entries_are_skipped_or_marked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::create_dir_all(root.join("src/target")).unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("src/target/out"), vec![0u8; 200]).unwrap();
+        fs::write(root.join("src/main.rs"), vec![0u8; 10]).unwrap();
+        fs::write(root.join("src").join(IGNORE_FILE), "target\n").unwrap();
+
+        let mut options = ScanOptions {
+            exclude: ExcludeRules::new(&["node_modules".into()], &[]).unwrap(),
+            ..Default::default()
+        };
+        let node = scan(&root, &options);
+        assert!(node.child("node_modules").is_none());
+        assert!(node.child("src").unwrap().child("target").is_none());
+        // main.rs plus the ignore file itself
+        assert_eq!(node.size, 10 + "target\n".len() as u64);
+
+        options.show_excluded = true;
+        let node = scan(&root, &options);
+        let excluded = node.child("node_modules").unwrap();
+        assert!(excluded.excluded && excluded.is_dir);
+        assert_eq!(excluded.size, 0);
+        assert!(node.child("src").unwrap().child("target").unwrap().excluded);
+    }
+
+    #[test]
+    fn staging_directories_are_left_out() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join(".mcdu-staged-1000/1")).unwrap();
+        fs::write(root.join(".mcdu-staged-1000/1/old.iso"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("kept"), vec![0u8; 10]).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+        assert!(node.child(".mcdu-staged-1000").is_none());
+        assert_eq!(node.size, 10);
+
+        let options = ScanOptions {
+            show_excluded: true,
+            ..Default::default()
+        };
+        let node = scan(&root, &options);
+        assert!(node.child(".mcdu-staged-1000").unwrap().excluded);
+        assert_eq!(node.size, 10);
+    }
+
+    #[test]
+    fn cancelled_scan_stops_descending() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/f"), vec![0u8; 100]).unwrap();
+
+        let cache = SizeCache::new();
+        let cancel = AtomicBool::new(true);
+        let node = scan_tree(
+            &root,
+            &cache,
+            None,
+            HashSet::new(),
+            &ScanOptions::default(),
+            &cancel,
+            None,
+        )
+        .unwrap()
+        .node;
+
+        assert!(node.partial);
+        assert_eq!(node.size, 0);
+        // Nothing from an aborted walk may be cached
+        assert_eq!(
+            cache.get(&root.join("a"), &fs::metadata(root.join("a")).unwrap()),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directories_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("locked")).unwrap();
+        fs::write(root.join("locked/f"), vec![0u8; 10]).unwrap();
+        fs::write(root.join("g"), vec![0u8; 5]).unwrap();
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions don't apply to root
+        if fs::read_dir(root.join("locked")).is_ok() {
+            return;
+        }
+
+        let cache = SizeCache::new();
+        let output = scan_tree(
+            &root,
+            &cache,
+            None,
+            HashSet::new(),
+            &ScanOptions::default(),
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap();
+
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.errors[0].path, root.join("locked"));
+        assert_eq!(output.errors[0].kind, "EACCES");
+        assert_eq!(output.node.child("locked").unwrap().error_count, 1);
+        assert_eq!(output.node.error_count, 1);
+        assert_eq!(output.node.size, 5);
+        // Incomplete sizes must not be cached
+        assert_eq!(
+            cache.get(
+                &root.join("locked"),
+                &fs::metadata(root.join("locked")).unwrap()
+            ),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_scanned_and_escaped() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let name = OsStr::from_bytes(b"caf\xe9\n.txt");
+        fs::write(root.join(name), vec![0u8; 42]).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+        assert_eq!(node.size, 42);
+        assert_eq!(node.child(name).unwrap().size, 42);
+        assert_eq!(display_name(name), "caf\\xe9\\n.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn item_counts_cover_files_dirs_and_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/f"), b"x").unwrap();
+        fs::write(root.join("a/b/g"), b"y").unwrap();
+        std::os::unix::fs::symlink("f", root.join("a/link")).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+        let a = node.child("a").unwrap();
+        assert_eq!(a.file_count, 2);
+        assert_eq!(a.dir_count, 1);
+        assert_eq!(a.symlink_count, 1);
+        assert_eq!(node.dir_count, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inode_mode_sizes_entries_by_item_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("many/sub")).unwrap();
+        for i in 0..5 {
+            fs::write(root.join("many/sub").join(i.to_string()), b"").unwrap();
+        }
+        fs::write(root.join("big"), vec![0u8; 4096]).unwrap();
+
+        let node = scan(&root, &ScanOptions::default());
+        let entries = crate::tree::DirTree::new(root.clone(), node)
+            .entries(&root)
+            .unwrap();
+        let many = entries.iter().find(|e| e.name == "many").unwrap();
+        let big = entries.iter().find(|e| e.name == "big").unwrap();
+
+        // Five files and a directory outnumber one large file
+        assert_eq!(many.size_for(SizeMode::Inodes), 6);
+        assert_eq!(big.size_for(SizeMode::Inodes), 1);
+        assert_eq!(big.size_for(SizeMode::Apparent), 4096);
+        assert!(!SizeMode::Inodes.is_bytes());
+        assert_eq!(SizeMode::Disk.toggle(), SizeMode::Inodes);
+        assert_eq!(SizeMode::Inodes.toggle(), SizeMode::Apparent);
+    }
+
+    #[test]
+    fn cached_subtrees_stand_in_without_previous_tree() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/f"), vec![0u8; 300]).unwrap();
+
+        let cache = SizeCache::new();
+        let never = AtomicBool::new(false);
+        let options = ScanOptions::default();
+        let first = scan_tree(&root, &cache, None, HashSet::new(), &options, &never, None)
+            .unwrap()
+            .node;
+
+        // Like a fresh session with a loaded cache: no previous tree
+        let second = scan_tree(&root, &cache, None, HashSet::new(), &options, &never, None)
+            .unwrap()
+            .node;
+        let a = second.child("a").unwrap();
+        assert!(a.from_cache);
+        assert!(a.children.is_empty());
+        assert_eq!(Totals::of(a), Totals::of(first.child("a").unwrap()));
+        assert_eq!(second.size, 300);
+
+        // A file growing in place doesn't touch any directory mtime
+        let mut f = fs::OpenOptions::new()
+            .append(true)
+            .open(root.join("a/b/f"))
+            .unwrap();
+        io::Write::write_all(&mut f, &[0u8; 100]).unwrap();
+        let third = scan_tree(&root, &cache, None, HashSet::new(), &options, &never, None)
+            .unwrap()
+            .node;
+        assert!(!third.child("a").unwrap().from_cache);
+        assert_eq!(third.size, 400);
+    }
+
+    #[test]
+    fn scans_on_the_callers_pool() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/f"), vec![0u8; 300]).unwrap();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let never = AtomicBool::new(false);
+        let options = ScanOptions::default();
+        let size = pool.install(|| {
+            scan_tree(
+                &root,
+                &SizeCache::new(),
+                None,
+                HashSet::new(),
+                &options,
+                &never,
+                None,
+            )
+            .map(|output| output.node.size)
+            .ok()
+        });
+        assert_eq!(size, Some(300));
+    }
 }
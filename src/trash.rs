@@ -0,0 +1,232 @@
+// Move to trash following the FreeDesktop.org Trash specification
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Move `path` into the home trash or the one at the top of its mount,
+/// whichever is on its filesystem. Returns its new location.
+#[cfg(unix)]
+pub fn move_to_trash(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = std::path::absolute(path)?;
+    let home_trash = home_trash_dir();
+    if same_device(&path, &home_trash) {
+        return Ok(move_to_trash_in(&path, &home_trash, None)?);
+    }
+
+    let top = mount_top(&path)?;
+    let uid = nix::unistd::getuid().as_raw();
+    let trash = match shared_trash(&top) {
+        Some(shared) => shared.join(uid.to_string()),
+        None => top.join(format!(".Trash-{}", uid)),
+    };
+    Ok(move_to_trash_in(&path, &trash, Some(&top))?)
+}
+
+#[cfg(not(unix))]
+pub fn move_to_trash(_path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Err("moving to the trash is only supported on Unix".into())
+}
+
+/// Move `path` into `trash`; for per-mount trashes the recorded path is
+/// relative to the mount's `top`
+pub fn move_to_trash_in(path: &Path, trash: &Path, top: Option<&Path>) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash a root"))?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    create_private_dir(&files)?;
+    create_private_dir(&info)?;
+
+    let recorded = top
+        .and_then(|top| path.strip_prefix(top).ok())
+        .unwrap_or(path);
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(recorded),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    // Claim a free name by creating its .trashinfo exclusively, as the spec
+    // requires, so concurrent trashers never pick the same one
+    for attempt in 1u32.. {
+        let mut trashed_name = name.to_os_string();
+        if attempt > 1 {
+            trashed_name.push(format!(".{}", attempt));
+        }
+        let mut info_name = trashed_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        let files_path = files.join(&trashed_name);
+
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if files_path.symlink_metadata().is_ok() {
+            // Left behind without an info file, don't overwrite it
+            drop(file);
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+        io::Write::write_all(&mut file, contents.as_bytes())?;
+
+        if let Err(e) = fs::rename(path, &files_path) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok(files_path);
+    }
+    unreachable!("ran out of trash names")
+}
+
+/// `$XDG_DATA_HOME/Trash`, defaulting to `~/.local/share/Trash`
+fn home_trash_dir() -> PathBuf {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".local").join("share")
+        });
+    data_home.join("Trash")
+}
+
+/// Whether `path` is on the same filesystem as the closest existing
+/// ancestor of `other`
+#[cfg(unix)]
+pub fn same_device(path: &Path, other: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(device) = path.symlink_metadata().map(|m| m.dev()) else {
+        return false;
+    };
+    other
+        .ancestors()
+        .find_map(|dir| fs::metadata(dir).ok())
+        .is_some_and(|m| m.dev() == device)
+}
+
+/// Top directory of the mount containing `path`
+#[cfg(unix)]
+pub fn mount_top(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = path.symlink_metadata()?.dev();
+    let mut top = path.parent().unwrap_or(path);
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent)?.dev() != device {
+            break;
+        }
+        top = parent;
+    }
+    Ok(top.to_path_buf())
+}
+
+/// `$topdir/.Trash` if an administrator set it up: a real directory with the
+/// sticky bit. Otherwise it must not be used.
+#[cfg(unix)]
+fn shared_trash(top: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let shared = top.join(".Trash");
+    let metadata = shared.symlink_metadata().ok()?;
+    (metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0).then_some(shared)
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+/// Percent-encode a path for the `Path=` key (RFC 2396 escaping of the raw
+/// bytes, keeping `/` and unreserved characters)
+pub fn encode_path(path: &Path) -> String {
+    let mut out = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()/".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Reverse of `encode_path`; malformed escapes are kept literally
+pub fn decode_path(encoded: &str) -> PathBuf {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(value) => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(crate::scan::bytes_to_os_string(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trashed_entries_get_unique_names_and_info_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("data/my logs")).unwrap();
+        fs::write(root.join("data/my logs/app.log"), b"line\n").unwrap();
+        let trash = root.join("Trash");
+
+        let first = move_to_trash_in(&root.join("data/my logs"), &trash, None).unwrap();
+        assert!(!root.join("data/my logs").exists());
+        assert_eq!(first, trash.join("files/my logs"));
+        assert!(first.join("app.log").exists());
+        let info = fs::read_to_string(trash.join("info/my logs.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!("Path={}/data/my%20logs\n", encode_path(&root))));
+        assert!(info.contains("DeletionDate="));
+
+        // Same name again, relative to a mount top
+        fs::create_dir_all(root.join("data/my logs")).unwrap();
+        let second = move_to_trash_in(&root.join("data/my logs"), &trash, Some(&root)).unwrap();
+        assert_eq!(second, trash.join("files/my logs.2"));
+        let info = fs::read_to_string(trash.join("info/my logs.2.trashinfo")).unwrap();
+        assert!(info.contains("Path=data/my%20logs\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encoded_paths_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/data/caf\xe9 100%/a:b"));
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "/data/caf%E9%20100%25/a%3Ab");
+        assert_eq!(decode_path(&encoded), path);
+    }
+}
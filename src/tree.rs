@@ -0,0 +1,340 @@
+use crate::scan::DirEntry;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// A single file or directory in the scanned tree
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// Raw file name, may not be valid UTF-8
+    pub name: OsString,
+    /// Apparent size in bytes
+    pub size: u64,
+    /// Allocated size on disk (st_blocks * 512)
+    pub disk_size: u64,
+    /// Bytes of hard links whose inode was already counted elsewhere
+    pub shared_size: u64,
+    pub is_dir: bool,
+    /// Files in this subtree (1 for a file itself)
+    pub file_count: u64,
+    /// Directories below this one, not counting itself
+    pub dir_count: u64,
+    /// Symlinks in this subtree (1 for a symlink itself)
+    pub symlink_count: u64,
+    /// Subtree was not fully scanned (file limit reached)
+    pub partial: bool,
+    /// Mount point of another filesystem that was not descended into
+    pub other_fs: bool,
+    /// Matched an exclude rule and was not measured
+    pub excluded: bool,
+    /// Totals restored from the size cache, children not loaded yet
+    pub from_cache: bool,
+    /// Paths in this subtree that could not be read
+    pub error_count: u64,
+    /// Modification time in seconds since the epoch, 0 if unknown
+    pub mtime: u64,
+    /// (dev, ino) of a file with more than one hard link
+    pub hardlink: Option<(u64, u64)>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn file(name: OsString, size: u64, disk_size: u64) -> Self {
+        Node {
+            name,
+            size,
+            disk_size,
+            shared_size: 0,
+            is_dir: false,
+            file_count: 1,
+            dir_count: 0,
+            symlink_count: 0,
+            partial: false,
+            other_fs: false,
+            excluded: false,
+            from_cache: false,
+            error_count: 0,
+            mtime: 0,
+            hardlink: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Directory node whose totals are the sum of its children
+    pub fn dir(name: OsString, children: Vec<Node>) -> Self {
+        Node {
+            name,
+            size: children.iter().map(|c| c.size).sum(),
+            disk_size: children.iter().map(|c| c.disk_size).sum(),
+            shared_size: children.iter().map(|c| c.shared_size).sum(),
+            is_dir: true,
+            file_count: children.iter().map(|c| c.file_count).sum(),
+            dir_count: children
+                .iter()
+                .map(|c| c.dir_count + u64::from(c.is_dir && !c.excluded))
+                .sum(),
+            symlink_count: children.iter().map(|c| c.symlink_count).sum(),
+            partial: children.iter().any(|c| c.partial),
+            other_fs: false,
+            excluded: false,
+            from_cache: false,
+            error_count: children.iter().map(|c| c.error_count).sum(),
+            mtime: 0,
+            hardlink: None,
+            children,
+        }
+    }
+
+    /// Directory node standing in for a subtree whose totals came from the cache
+    pub fn cached_dir(name: OsString, totals: Totals) -> Self {
+        let mut node = Node::dir(name, Vec::new());
+        totals.add_to(&mut node);
+        node.from_cache = true;
+        node
+    }
+
+    pub fn child(&self, name: impl AsRef<OsStr>) -> Option<&Node> {
+        let name = name.as_ref();
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn child_mut(&mut self, name: &OsStr) -> Option<&mut Node> {
+        self.children.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Add the hard-linked inodes whose bytes this subtree counts, skipping `except`
+    fn counted_links(&self, except: &Node, links: &mut HashSet<(u64, u64)>) {
+        if std::ptr::eq(self, except) {
+            return;
+        }
+        if let Some(key) = self.hardlink.filter(|_| self.shared_size == 0) {
+            links.insert(key);
+        }
+        for child in &self.children {
+            child.counted_links(except, links);
+        }
+    }
+}
+
+/// Persistent in-memory tree built by one recursive scan.
+/// Navigation walks this tree instead of rescanning the filesystem.
+pub struct DirTree {
+    pub root_path: PathBuf,
+    pub root: Node,
+}
+
+impl DirTree {
+    pub fn new(root_path: PathBuf, root: Node) -> Self {
+        DirTree { root_path, root }
+    }
+
+    /// Find the node for an absolute path inside the tree
+    pub fn find(&self, path: &Path) -> Option<&Node> {
+        let relative = path.strip_prefix(&self.root_path).ok()?;
+        let mut node = &self.root;
+        for component in relative.components() {
+            node = node.child(component.as_os_str())?;
+        }
+        Some(node)
+    }
+
+    /// Hard-linked inodes counted outside the subtree at `path`, so a rescan
+    /// of that subtree counts them as shared. Empty if `path` isn't in the tree.
+    pub fn counted_links_outside(&self, path: &Path) -> HashSet<(u64, u64)> {
+        let mut links = HashSet::new();
+        if let Some(subtree) = self.find(path) {
+            self.root.counted_links(subtree, &mut links);
+        }
+        links
+    }
+
+    /// Replace the subtree at `path` with a freshly scanned node,
+    /// propagating the size difference to all ancestors.
+    /// Returns false if the path is not part of the tree.
+    pub fn replace(&mut self, path: &Path, mut node: Node) -> bool {
+        let relative = match path.strip_prefix(&self.root_path) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        let names: Vec<&OsStr> = relative.components().map(|c| c.as_os_str()).collect();
+
+        // Walk down once to validate the path and compute the delta
+        let mut target = &self.root;
+        for name in &names {
+            match target.child(name) {
+                Some(child) => target = child,
+                None => return false,
+            }
+        }
+        let old = Totals::of(target);
+        let new = Totals::of(&node);
+
+        // Walk down again, adjusting every ancestor on the way
+        let mut current = &mut self.root;
+        for name in &names {
+            old.subtract_from(current);
+            new.add_to(current);
+            current = current.child_mut(name).expect("path validated above");
+        }
+
+        // Keep the name the tree knows this node by (the root may be named by its full path)
+        node.name = std::mem::take(&mut current.name);
+        *current = node;
+
+        // Ancestors are partial if anything below them still is
+        for depth in (0..names.len()).rev() {
+            let mut ancestor = &mut self.root;
+            for name in &names[..depth] {
+                ancestor = ancestor.child_mut(name).expect("path validated above");
+            }
+            ancestor.partial = ancestor.children.iter().any(|c| c.partial);
+        }
+        true
+    }
+
+    /// Build the browser entries for a directory in the tree
+    pub fn entries(&self, path: &Path) -> Option<Vec<DirEntry>> {
+        let node = self.find(path)?;
+        Some(
+            node.children
+                .iter()
+                .map(|child| DirEntry {
+                    path: path.join(&child.name),
+                    name: child.name.clone(),
+                    size: child.size,
+                    disk_size: child.disk_size,
+                    shared_size: child.shared_size,
+                    is_dir: child.is_dir,
+                    file_count: child.file_count,
+                    dir_count: child.dir_count,
+                    symlink_count: child.symlink_count,
+                    partial: child.partial,
+                    other_fs: child.other_fs,
+                    excluded: child.excluded,
+                    error_count: child.error_count,
+                    mtime: child.mtime,
+                    size_change: None,
+                    is_new: false,
+                    removed: false,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Aggregates that ancestors accumulate from their descendants
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Totals {
+    pub size: u64,
+    pub disk_size: u64,
+    pub shared_size: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    pub symlink_count: u64,
+    pub error_count: u64,
+}
+
+impl Totals {
+    pub fn add(&mut self, other: &Totals) {
+        self.size += other.size;
+        self.disk_size += other.disk_size;
+        self.shared_size += other.shared_size;
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+        self.symlink_count += other.symlink_count;
+        self.error_count += other.error_count;
+    }
+
+    pub fn of(node: &Node) -> Self {
+        Totals {
+            size: node.size,
+            disk_size: node.disk_size,
+            shared_size: node.shared_size,
+            file_count: node.file_count,
+            dir_count: node.dir_count,
+            symlink_count: node.symlink_count,
+            error_count: node.error_count,
+        }
+    }
+
+    fn subtract_from(&self, node: &mut Node) {
+        node.size = node.size.saturating_sub(self.size);
+        node.disk_size = node.disk_size.saturating_sub(self.disk_size);
+        node.shared_size = node.shared_size.saturating_sub(self.shared_size);
+        node.file_count = node.file_count.saturating_sub(self.file_count);
+        node.dir_count = node.dir_count.saturating_sub(self.dir_count);
+        node.symlink_count = node.symlink_count.saturating_sub(self.symlink_count);
+        node.error_count = node.error_count.saturating_sub(self.error_count);
+    }
+
+    fn add_to(&self, node: &mut Node) {
+        node.size += self.size;
+        node.disk_size += self.disk_size;
+        node.shared_size += self.shared_size;
+        node.file_count += self.file_count;
+        node.dir_count += self.dir_count;
+        node.symlink_count += self.symlink_count;
+        node.error_count += self.error_count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, size: u64) -> Node {
+        Node::file(name.into(), size, size.div_ceil(4096) * 4096)
+    }
+
+    fn dir(name: &str, children: Vec<Node>) -> Node {
+        Node::dir(name.into(), children)
+    }
+
+    #[test]
+    fn find_walks_relative_components() {
+        let tree = DirTree::new(
+            PathBuf::from("/data"),
+            dir("/data", vec![dir("a", vec![file("b", 10)])]),
+        );
+
+        assert_eq!(tree.find(Path::new("/data/a/b")).unwrap().size, 10);
+        assert!(tree.find(Path::new("/data/missing")).is_none());
+        assert!(tree.find(Path::new("/elsewhere")).is_none());
+    }
+
+    #[test]
+    fn replace_propagates_size_to_ancestors() {
+        let mut tree = DirTree::new(
+            PathBuf::from("/data"),
+            dir(
+                "/data",
+                vec![dir("a", vec![dir("b", vec![file("f", 10)])]), file("g", 5)],
+            ),
+        );
+
+        let rescanned = dir("b", vec![file("f", 10), file("h", 30)]);
+        assert!(tree.replace(Path::new("/data/a/b"), rescanned));
+
+        assert_eq!(tree.root.size, 45);
+        assert_eq!(tree.root.disk_size, 3 * 4096);
+        assert_eq!(tree.root.file_count, 3);
+        assert_eq!(tree.root.dir_count, 2);
+        assert_eq!(tree.find(Path::new("/data/a")).unwrap().size, 40);
+        assert_eq!(tree.find(Path::new("/data/a/b")).unwrap().children.len(), 2);
+    }
+
+    #[test]
+    fn replace_clears_partial_on_ancestors() {
+        let mut partial_dir = dir("b", Vec::new());
+        partial_dir.partial = true;
+        let mut tree = DirTree::new(
+            PathBuf::from("/data"),
+            dir("/data", vec![dir("a", vec![partial_dir])]),
+        );
+        assert!(tree.root.partial);
+
+        assert!(tree.replace(Path::new("/data/a/b"), dir("b", vec![file("f", 1)])));
+        assert!(!tree.root.partial);
+        assert!(!tree.find(Path::new("/data/a")).unwrap().partial);
+    }
+}
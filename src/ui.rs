@@ -1,10 +1,12 @@
-use crate::app::App;
+use crate::app::{format_timestamp, App};
+use crate::changes::{days_until_full, growth_per_day};
 use crate::modal::Modal;
+use crate::scan::SortMode;
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, Gauge, Paragraph},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph, Sparkline},
     Frame,
 };
 
@@ -16,6 +18,7 @@ pub fn draw(f: &mut Frame, app: &App) {
             Constraint::Length(1),
             Constraint::Min(10),
             Constraint::Length(1),
+            Constraint::Length(1),
         ])
         .split(f.area());
 
@@ -25,8 +28,11 @@ pub fn draw(f: &mut Frame, app: &App) {
     // Main content area
     draw_browser(f, app, chunks[1]);
 
+    // Details of the selected entry
+    draw_details(f, app, chunks[2]);
+
     // Help/status bar
-    draw_footer(f, chunks[2]);
+    draw_footer(f, chunks[3]);
 
     // Notification if present
     if let Some(notif) = &app.notification {
@@ -48,38 +54,104 @@ pub fn draw(f: &mut Frame, app: &App) {
         draw_loading(f, app.scanning_name.as_deref(), app.scan_progress);
     }
 
+    // Errors panel if shown
+    if app.show_errors {
+        draw_errors(f, app);
+    }
+
+    // Size history of the selected entry
+    if app.show_history {
+        draw_history(f, app);
+    }
+
     // Help screen if shown
     if app.show_help {
         draw_help(f);
     }
 }
 
+/// Width of the title bar kept for the current path
+const TITLE_MIN_WIDTH: u16 = 30;
+
 fn draw_title(f: &mut Frame, app: &App, area: Rect) {
     let title_text = format!(" 📊 mcdu v0.2.0 | {} ", app.current_path.display());
 
     let right_text = if app.is_scanning {
         "  ⟳ Scanning... ".to_string()
+    } else if app.scan_cancelled {
+        "  ⊘ Scan cancelled - [r] to rescan ".to_string()
     } else {
-        let cache_size = app.size_cache.size();
-        let mut info = format!("  {} items | {} cached", app.entries.len(), cache_size);
+        // (priority, text) in display order, lower priority numbers are kept longer
+        let mut segments = vec![(1, format!("{} items", app.entries.len()))];
+
+        let cache = app.size_cache.stats();
+        segments.push((
+            8,
+            format!(
+                "{} cached, {} hits, {} misses, {} evicted",
+                format_count(cache.entries as u64),
+                format_count(cache.hits),
+                format_count(cache.misses),
+                format_count(cache.evictions)
+            ),
+        ));
+        segments.push((
+            7,
+            format!("{} | {}", app.size_mode.label(), app.sort_mode.label()),
+        ));
 
         // Add disk space if available
         if let Some(ref disk) = app.disk_space {
             let avail = format_size(disk.available_bytes);
             let total = format_size(disk.total_bytes);
             let percent_used = (disk.used_bytes as f64 / disk.total_bytes as f64 * 100.0) as u8;
-            info.push_str(&format!(" | 💾 {}/{} ({}%)", avail, total, percent_used));
+            segments.push((0, format!("💾 {}/{} ({}%)", avail, total, percent_used)));
+
+            if disk.total_inodes > 0 {
+                let percent_inodes =
+                    (disk.used_inodes as f64 / disk.total_inodes as f64 * 100.0) as u8;
+                segments.push((
+                    5,
+                    format!(
+                        "🗂 {}/{} inodes ({}%)",
+                        format_count(disk.used_inodes),
+                        format_count(disk.total_inodes),
+                        percent_inodes
+                    ),
+                ));
+            }
+        }
+
+        if let Some(snapshot) = app.history.baseline(app.baseline_time) {
+            segments.push((
+                3,
+                format!("Δ since {} [b]", format_timestamp(snapshot.timestamp)),
+            ));
+        }
+
+        if app.removed_count > 0 {
+            segments.push((4, format!("✗ {} removed [g]", app.removed_count)));
+        }
+
+        if !app.scan_errors.is_empty() {
+            segments.push((2, format!("⚠ {} errors [e]", app.scan_errors.len())));
         }
 
-        format!("{} ", info)
+        if let Some(watcher) = &app.watcher {
+            segments.push((6, format!("👁 {} dirs", watcher.watch_count())));
+        }
+
+        let room = area.width.saturating_sub(TITLE_MIN_WIDTH + 2) as usize;
+        format!("  {} ", fit_segments(segments, room.saturating_sub(3)))
     };
 
-    // Layout for title bar
+    // Layout for title bar, sized by display width since emoji take two columns
+    let right_width = Line::from(right_text.as_str()).width() as u16;
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Min(30),
-            Constraint::Length(right_text.len() as u16 + 2),
+            Constraint::Min(TITLE_MIN_WIDTH),
+            Constraint::Length(right_width + 2),
         ])
         .split(area);
 
@@ -94,7 +166,7 @@ fn draw_title(f: &mut Frame, app: &App, area: Rect) {
         chunks[0],
     );
 
-    let right_style = if app.is_scanning {
+    let right_style = if app.is_scanning || app.scan_cancelled {
         Style::default()
             .fg(Color::Yellow)
             .add_modifier(Modifier::BOLD)
@@ -110,6 +182,28 @@ fn draw_title(f: &mut Frame, app: &App, area: Rect) {
     );
 }
 
+/// Join `(priority, text)` segments with " | ", dropping the lowest priority
+/// ones (highest numbers) until the result is at most `max_width` columns wide
+fn fit_segments(mut segments: Vec<(u8, String)>, max_width: usize) -> String {
+    loop {
+        let text = segments
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<_>>()
+            .join(" | ");
+        if segments.len() <= 1 || Line::from(text.as_str()).width() <= max_width {
+            return text;
+        }
+        let lowest = segments
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (priority, _))| *priority)
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        segments.remove(lowest);
+    }
+}
+
 fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
     let mut lines = Vec::new();
 
@@ -125,8 +219,8 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
     let total_size: u64 = app
         .entries
         .iter()
-        .filter(|entry| entry.name != "..")
-        .map(|entry| entry.size)
+        .filter(|entry| entry.name != ".." && !entry.removed)
+        .map(|entry| entry.size_for(app.size_mode))
         .sum();
 
     // Directory entries - only render visible items
@@ -138,24 +232,84 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
         .take(end_idx - start_idx)
     {
         let is_selected = idx == app.selected_index;
-        let size_str = format_size(entry.size);
-        let percent_bar = if entry.size > 0 {
-            create_bar(entry.size, 100_000_000_000) // 100GB as max
+        let size = entry.size_for(app.size_mode);
+        let formatted = if app.size_mode.is_bytes() {
+            format_size(size)
+        } else {
+            format_count(size)
+        };
+        // Partial sizes are lower bounds, other filesystems were never measured
+        let size_str = if entry.removed {
+            // Only the apparent size is kept in snapshots
+            format_size(entry.size)
+        } else if entry.other_fs {
+            "mount".to_string()
+        } else if entry.excluded {
+            "excluded".to_string()
+        } else if entry.partial {
+            format!("≥{}", formatted)
+        } else {
+            formatted
+        };
+        let percent_bar = if entry.removed {
+            String::new()
+        } else if size > 0 && app.size_mode.is_bytes() {
+            create_bar(size, 100_000_000_000) // 100GB as max
+        } else if size > 0 {
+            create_bar(size, 1_000_000) // 1M entries as max
         } else {
             String::new()
         };
-        let percent_of_total = if total_size > 0 && entry.name != ".." {
-            (entry.size as f64 / total_size as f64) * 100.0
+        let percent_of_total = if total_size > 0 && entry.name != ".." && !entry.removed {
+            (size as f64 / total_size as f64) * 100.0
         } else {
             0.0
         };
         let percent_str = format!("{:>4.0}%", percent_of_total.round());
 
-        let size_color = get_color_by_size(entry.size);
-        let name_prefix = if entry.is_dir { "📁 " } else { "📄 " };
+        let size_color = if entry.other_fs {
+            Color::Blue
+        } else if entry.excluded {
+            Color::DarkGray
+        } else if app.size_mode.is_bytes() {
+            get_color_by_size(size)
+        } else {
+            get_color_by_count(size)
+        };
+        let name_prefix = if entry.removed {
+            "👻 "
+        } else if entry.other_fs {
+            "💽 "
+        } else if entry.is_dir {
+            "📁 "
+        } else {
+            "📄 "
+        };
 
-        // Check for size changes
-        let (name_style, change_indicator) = if let Some((delta, percent)) = entry.size_change {
+        // Check for removed, new and resized entries
+        let (name_style, change_indicator) = if entry.removed {
+            let ghost_style = Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::CROSSED_OUT);
+            let ghost_style = if is_selected {
+                ghost_style.bg(Color::Gray).fg(Color::Black)
+            } else {
+                ghost_style
+            };
+            (ghost_style, " ✗ removed".to_string())
+        } else if entry.is_new {
+            let new_style = if is_selected {
+                Style::default()
+                    .bg(Color::Green)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD)
+            };
+            (new_style, " NEW".to_string())
+        } else if let Some((delta, percent)) = entry.size_change {
             let change_style = if delta > 0 {
                 // Size increased - highlight in yellow/red
                 if is_selected {
@@ -189,6 +343,13 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
             };
 
             (change_style, indicator)
+        } else if entry.excluded {
+            let name_style = if is_selected {
+                Style::default().bg(Color::DarkGray).fg(Color::Gray)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            (name_style, String::new())
         } else {
             let name_style = if is_selected {
                 Style::default()
@@ -208,7 +369,7 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
                 format!(
                     "{}{:<25}",
                     name_prefix,
-                    &entry.name[..entry.name.len().min(25)]
+                    entry.display_name().chars().take(25).collect::<String>()
                 ),
                 name_style,
             ),
@@ -224,10 +385,42 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
             line_spans.push(Span::styled(change_indicator, name_style));
         }
 
-        if entry.file_count > 1 {
+        if entry.other_fs {
             line_spans.push(Span::styled(
-                format!("({} items)", entry.file_count),
-                Style::default().fg(Color::Gray),
+                "[other filesystem] ",
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+
+        if entry.partial {
+            line_spans.push(Span::styled(
+                "[partial] ",
+                Style::default()
+                    .fg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+
+        if entry.error_count > 0 {
+            line_spans.push(Span::styled(
+                format!("[⚠ {}] ", entry.error_count),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+
+        if entry.is_dir && entry.name != ".." {
+            // Item counts are highlighted when they drive the sort order
+            let count_style = match app.sort_mode {
+                SortMode::Size => Style::default().fg(Color::Gray),
+                SortMode::Items => Style::default()
+                    .fg(get_color_by_count(entry.item_count()))
+                    .add_modifier(Modifier::BOLD),
+            };
+            line_spans.push(Span::styled(
+                format!("({} items)", entry.item_count()),
+                count_style,
             ));
         }
 
@@ -241,6 +434,75 @@ fn draw_browser(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(Paragraph::new(lines).block(block), area);
 }
 
+/// Height of the browser block (including its header lines) for a terminal of the given height
+pub fn browser_height(terminal_height: u16) -> usize {
+    // Margins (2), title, details and footer rows (3), block borders (2)
+    terminal_height.saturating_sub(7) as usize
+}
+
+fn draw_details(f: &mut Frame, app: &App, area: Rect) {
+    let entry = match app.entries.get(app.selected_index) {
+        Some(entry) if entry.name != ".." => entry,
+        _ => return,
+    };
+
+    if entry.removed {
+        let line = Line::from(vec![
+            Span::styled(
+                format!(" {} ", entry.display_name()),
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(
+                    "removed since the compared scan, was {}",
+                    format_size(entry.size)
+                ),
+                Style::default().fg(Color::Gray),
+            ),
+        ]);
+        f.render_widget(Paragraph::new(line), area);
+        return;
+    }
+
+    let mut spans = vec![
+        Span::styled(
+            format!(" {} ", entry.display_name()),
+            Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!(
+                "{} apparent · {} on disk",
+                format_size(entry.size),
+                format_size(entry.disk_size)
+            ),
+            Style::default().fg(Color::Gray),
+        ),
+    ];
+
+    if entry.is_dir {
+        spans.push(Span::styled(
+            format!(
+                " · {} files, {} dirs, {} symlinks",
+                entry.file_count, entry.dir_count, entry.symlink_count
+            ),
+            Style::default().fg(Color::Gray),
+        ));
+    }
+
+    if entry.shared_size > 0 {
+        spans.push(Span::styled(
+            format!(" · {} shared via hardlinks", format_size(entry.shared_size)),
+            Style::default().fg(Color::Blue),
+        ));
+    }
+
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
 fn draw_footer(f: &mut Frame, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -338,13 +600,20 @@ fn draw_progress(f: &mut Frame, progress: &crate::app::DeleteProgress) {
         .constraints([
             Constraint::Length(2),
             Constraint::Length(3),
-            Constraint::Length(2),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
         ])
         .split(centered);
 
-    // Status
+    // Status, with the abort key while it can still be used
+    let status = if progress.cancelling {
+        format!("🗑️  {}", progress.status)
+    } else {
+        format!("🗑️  {}   [Esc] abort", progress.status)
+    };
     f.render_widget(
-        Paragraph::new(format!("🗑️  {}", progress.status)).style(
+        Paragraph::new(status).style(
             Style::default()
                 .fg(Color::Yellow)
                 .add_modifier(Modifier::BOLD)
@@ -354,12 +623,7 @@ fn draw_progress(f: &mut Frame, progress: &crate::app::DeleteProgress) {
     );
 
     // Progress gauge
-    let ratio = if progress.total_bytes > 0 {
-        progress.deleted_bytes as f64 / progress.total_bytes as f64
-    } else {
-        0.0
-    };
-
+    let ratio = progress.ratio();
     let gauge = Gauge::default()
         .block(Block::default().borders(Borders::ALL))
         .gauge_style(Style::default().fg(Color::Green))
@@ -370,15 +634,178 @@ fn draw_progress(f: &mut Frame, progress: &crate::app::DeleteProgress) {
 
     // Stats
     let stats = format!(
-        "Deleted: {} / {} ({} files)",
+        "Deleted: {} / {} ({} / {} files)",
         format_size(progress.deleted_bytes),
         format_size(progress.total_bytes),
-        progress.deleted_files
+        format_count(progress.deleted_files),
+        format_count(progress.total_files)
     );
     f.render_widget(
         Paragraph::new(stats).style(Style::default().bg(Color::Black)),
         inner_layout[2],
     );
+
+    // Throughput and time left
+    let rate = match progress.eta() {
+        Some(eta) if progress.deleted_files > 0 => format!(
+            "{}/s, about {} left",
+            format_size(progress.throughput() as u64),
+            format_duration(eta)
+        ),
+        _ => String::new(),
+    };
+    f.render_widget(
+        Paragraph::new(rate).style(Style::default().fg(Color::Gray).bg(Color::Black)),
+        inner_layout[3],
+    );
+
+    // Current file, keeping the end of the path when it doesn't fit
+    let max_width = (inner_layout[4].width as usize).max(4);
+    let chars: Vec<char> = progress.current_file.chars().collect();
+    let current = if chars.len() > max_width {
+        let tail: String = chars[chars.len() - (max_width - 3)..].iter().collect();
+        format!("...{}", tail)
+    } else {
+        progress.current_file.clone()
+    };
+    f.render_widget(
+        Paragraph::new(current).style(Style::default().fg(Color::Cyan).bg(Color::Black)),
+        inner_layout[4],
+    );
+}
+
+fn draw_errors(f: &mut Frame, app: &App) {
+    let centered = centered_rect(80, 70, f.area());
+
+    // Clear the background first to prevent text bleed-through
+    f.render_widget(Clear, centered);
+
+    let mut lines = Vec::new();
+    if app.scan_errors.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No errors during the last scans",
+            Style::default().fg(Color::Gray),
+        )));
+    }
+    for error in app.scan_errors.iter().skip(app.errors_scroll) {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{:<8}", error.kind),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                error.path.display().to_string(),
+                Style::default().fg(Color::White),
+            ),
+            Span::styled(
+                format!("  {}", error.message),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+
+    let block = Block::default()
+        .title(format!(
+            " ⚠ Scan errors ({}) - [↑↓jk] scroll, any other key closes ",
+            app.scan_errors.len()
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .style(Style::default().bg(Color::Black)),
+        centered,
+    );
+}
+
+fn draw_history(f: &mut Frame, app: &App) {
+    let centered = centered_rect(80, 60, f.area());
+
+    // Clear the background first to prevent text bleed-through
+    f.render_widget(Clear, centered);
+
+    let Some((entry, points)) = app.selected_timeline() else {
+        return;
+    };
+
+    let block = Block::default()
+        .title(format!(
+            " 📈 History of {} - any key closes ",
+            entry.display_name()
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan))
+        .style(Style::default().bg(Color::Black));
+    let inner = block.inner(centered);
+    f.render_widget(block, centered);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(3)])
+        .split(inner);
+
+    let (first_time, first_size) = points[0];
+    let mut lines = vec![Line::from(format!(
+        "{} scans since {}: {} → {}",
+        points.len(),
+        format_timestamp(first_time),
+        format_size(first_size),
+        format_size(entry.size)
+    ))];
+
+    match growth_per_day(&points) {
+        None => lines.push(Line::from(Span::styled(
+            "Not enough history yet - open this directory again later",
+            Style::default().fg(Color::Gray),
+        ))),
+        Some(per_day) => {
+            let sign = if per_day < 0.0 { "-" } else { "+" };
+            lines.push(Line::from(format!(
+                "Growth: {}{}/day",
+                sign,
+                format_size(per_day.abs() as u64)
+            )));
+
+            if let Some(disk) = &app.disk_space {
+                let projection = match days_until_full(disk.available_bytes, per_day) {
+                    Some(days) => Span::styled(
+                        format!(
+                            "💾 {} free: this filesystem fills in ~{} days at this rate",
+                            format_size(disk.available_bytes),
+                            days.ceil() as u64
+                        ),
+                        Style::default()
+                            .fg(if days < 7.0 {
+                                Color::Red
+                            } else {
+                                Color::Yellow
+                            })
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    None => Span::styled(
+                        format!(
+                            "💾 {} free: not growing, no fill date",
+                            format_size(disk.available_bytes)
+                        ),
+                        Style::default().fg(Color::Green),
+                    ),
+                };
+                lines.push(Line::from(projection));
+            }
+        }
+    }
+    f.render_widget(Paragraph::new(lines), chunks[0]);
+
+    let sizes: Vec<u64> = points.iter().map(|&(_, size)| size).collect();
+    f.render_widget(
+        Sparkline::default()
+            .block(Block::default().title(" size per scan, oldest left "))
+            .data(&sizes)
+            .style(Style::default().fg(Color::Cyan)),
+        chunks[1],
+    );
 }
 
 fn draw_loading(f: &mut Frame, scanning_name: Option<&str>, progress: Option<(usize, usize)>) {
@@ -445,7 +872,7 @@ fn draw_loading(f: &mut Frame, scanning_name: Option<&str>, progress: Option<(us
 
     loading_text.push(Line::from(""));
     loading_text.push(Line::from(vec![Span::styled(
-        "Please wait",
+        "Please wait - [Esc] to cancel",
         Style::default().fg(Color::Gray),
     )]));
 
@@ -500,6 +927,34 @@ fn format_size(bytes: u64) -> String {
     format!("{:.1} {}", size, UNITS[unit_idx])
 }
 
+/// Rough time span, e.g. 42s, 3m 05s, 1h 12m
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {:02}s", s / 60, s % 60),
+        s => format!("{}h {:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// Compact entry count, e.g. 950, 12.3k, 4.1M
+fn format_count(count: u64) -> String {
+    const UNITS: &[&str] = &["", "k", "M", "G"];
+    let mut value = count as f64;
+    let mut unit_idx = 0;
+
+    while value >= 1000.0 && unit_idx < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit_idx += 1;
+    }
+
+    if unit_idx == 0 {
+        count.to_string()
+    } else {
+        format!("{:.1}{}", value, UNITS[unit_idx])
+    }
+}
+
 fn get_color_by_size(size: u64) -> Color {
     match size {
         s if s > 100_000_000_000 => Color::Red,   // >100GB
@@ -509,6 +964,15 @@ fn get_color_by_size(size: u64) -> Color {
     }
 }
 
+fn get_color_by_count(count: u64) -> Color {
+    match count {
+        c if c > 1_000_000 => Color::Red,  // >1M entries
+        c if c > 100_000 => Color::Yellow, // >100k
+        c if c > 10_000 => Color::Cyan,    // >10k
+        _ => Color::Green,
+    }
+}
+
 fn create_bar(current: u64, max: u64) -> String {
     let ratio = (current as f64 / max as f64).clamp(0.0, 1.0);
     let filled = (ratio * 10.0) as usize;
@@ -560,10 +1024,11 @@ pub fn draw_help(f: &mut Frame) {
             Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         )]),
         Line::from("  d                   Delete selected file/directory"),
-        Line::from("  y / n / d           Quick confirm in modals (yes/no/dry-run)"),
+        Line::from("  y / p / n / d       Quick keys in modals (trash/permanent/no/dry-run)"),
         Line::from("  ← / →               Navigate modal buttons (arrow keys)"),
         Line::from("  Enter               Confirm selected button"),
         Line::from("  Esc                 Close modal or quit"),
+        Line::from("  Esc                 Stop a running deletion between files"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "GENERAL",
@@ -572,7 +1037,16 @@ pub fn draw_help(f: &mut Frame) {
                 .add_modifier(Modifier::BOLD),
         )]),
         Line::from("  r                   Refresh current directory (uses cache)"),
-        Line::from("  c                   Clear cache and hard refresh"),
+        Line::from("  c                   Clear cache below this directory and rescan"),
+        Line::from("  Esc                 Cancel a running scan"),
+        Line::from("  a                   Cycle apparent size / disk usage / inodes"),
+        Line::from("  s                   Sort and colour by size / item count"),
+        Line::from("  e                   List paths that could not be read"),
+        Line::from("  w                   Watch mode: update sizes live (Linux)"),
+        Line::from("  b                   Compare with an older scan (cycles back)"),
+        Line::from("  t                   Size history, growth rate and fill projection"),
+        Line::from("  g                   Show / hide entries removed since that scan"),
+        Line::from("  u                   Undo the last deletion or move to trash"),
         Line::from("  ?                   Show this help screen"),
         Line::from("  q / Esc             Quit application"),
         Line::from(""),
@@ -584,20 +1058,36 @@ pub fn draw_help(f: &mut Frame) {
         )]),
         Line::from(vec![
             Span::styled("  ", Style::default().bg(Color::Red)),
-            Span::raw("  Red: >100 GB"),
+            Span::raw("  Red: >100 GB or >1M items"),
         ]),
         Line::from(vec![
             Span::styled("  ", Style::default().bg(Color::Yellow)),
-            Span::raw("  Yellow: 10-100 GB"),
+            Span::raw("  Yellow: 10-100 GB or >100k items"),
         ]),
         Line::from(vec![
             Span::styled("  ", Style::default().bg(Color::Cyan)),
-            Span::raw("  Cyan: 1-10 GB"),
+            Span::raw("  Cyan: 1-10 GB or >10k items"),
         ]),
         Line::from(vec![
             Span::styled("  ", Style::default().bg(Color::Green)),
             Span::raw("  Green: <1 GB"),
         ]),
+        Line::from(vec![
+            Span::styled("  ≥ [partial]", Style::default().fg(Color::Magenta)),
+            Span::raw("  File limit reached, size is a lower bound"),
+        ]),
+        Line::from(vec![
+            Span::styled("  💽 [other filesystem]", Style::default().fg(Color::Blue)),
+            Span::raw("  Mount point skipped by -x"),
+        ]),
+        Line::from(vec![
+            Span::styled("  excluded", Style::default().fg(Color::DarkGray)),
+            Span::raw("  Matched --exclude, config.json or .mcduignore"),
+        ]),
+        Line::from(vec![
+            Span::styled("  [⚠ N]", Style::default().fg(Color::Red)),
+            Span::raw("  Paths below that could not be read (see [e])"),
+        ]),
         Line::from(""),
         Line::from("Logs are saved to: ~/.mcdu/logs/"),
         Line::from(""),
@@ -616,3 +1106,25 @@ pub fn draw_help(f: &mut Frame) {
 
     f.render_widget(help_widget, centered);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_segments_drops_lowest_priority_by_display_width() {
+        let segments = vec![
+            (1, "3 items".to_string()),
+            (8, "cache".to_string()),
+            (0, "💾 1 GB".to_string()),
+        ];
+        assert_eq!(
+            fit_segments(segments.clone(), 80),
+            "3 items | cache | 💾 1 GB"
+        );
+        // The emoji is two columns wide: "3 items | 💾 1 GB" needs 17
+        assert_eq!(fit_segments(segments.clone(), 17), "3 items | 💾 1 GB");
+        assert_eq!(fit_segments(segments.clone(), 16), "💾 1 GB");
+        assert_eq!(fit_segments(segments, 0), "💾 1 GB");
+    }
+}
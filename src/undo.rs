@@ -0,0 +1,495 @@
+// Undo for deletions: staged deletes and trash moves are journaled as
+// transactions that can be rolled back until they are purged
+use crate::delete::{self, DeleteResult};
+use crate::logger;
+use crate::trash::{decode_path, encode_path};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Default time a staged delete can be undone before its space is freed
+pub const DEFAULT_GRACE_HOURS: u64 = 24;
+
+/// Distinguishes transactions started within the same second
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    /// Renamed into a staging directory, deleted for good when purged
+    Staged,
+    /// Moved to the trash, which keeps it after the transaction expires
+    Trash,
+}
+
+/// One undoable deletion. Paths are percent-encoded so any file name
+/// survives the JSON journal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: String,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+    pub kind: TransactionKind,
+    pub path: String,
+    pub moved_to: String,
+    pub size_bytes: u64,
+    pub files: u64,
+}
+
+impl Transaction {
+    /// Where the entry was before it was deleted
+    pub fn original_path(&self) -> PathBuf {
+        decode_path(&self.path)
+    }
+
+    /// Where the entry is now, in the staging area or the trash
+    pub fn moved_path(&self) -> PathBuf {
+        decode_path(&self.moved_to)
+    }
+
+    fn age_secs(&self) -> i64 {
+        chrono::Utc::now().timestamp() - self.timestamp
+    }
+}
+
+/// Rename `path` into a staging directory on its own filesystem and journal
+/// it, so it can be restored until it is purged
+pub fn stage(
+    path: &Path,
+    size_bytes: u64,
+    files: u64,
+) -> Result<Transaction, Box<dyn std::error::Error>> {
+    let path = std::path::absolute(path)?;
+    let name = path.file_name().ok_or("cannot delete a root directory")?;
+    let id = new_id();
+    let dir = staging_dir(&path)?.join(&id);
+    // Held until journaled, so a purge never takes the new directory for an orphan
+    let _lock = lock_journal()?;
+    fs::create_dir_all(&dir)?;
+    let moved_to = dir.join(name);
+    if let Err(e) = fs::rename(&path, &moved_to) {
+        let _ = fs::remove_dir(&dir);
+        return Err(e.into());
+    }
+
+    let transaction = Transaction {
+        id,
+        timestamp: chrono::Utc::now().timestamp(),
+        kind: TransactionKind::Staged,
+        path: encode_path(&path),
+        moved_to: encode_path(&moved_to),
+        size_bytes,
+        files,
+    };
+    let journaled = load_journal().and_then(|mut journal| {
+        journal.push(transaction.clone());
+        save_journal(&journal)
+    });
+    if let Err(e) = journaled {
+        // Without a journal entry nobody could restore or purge it
+        let _ = fs::rename(&moved_to, &path);
+        let _ = fs::remove_dir(&dir);
+        return Err(e.into());
+    }
+    Ok(transaction)
+}
+
+/// Whether `path` can be staged: its filesystem's staging directory exists
+/// or can be created (the top of a mount may not be writable)
+pub fn can_stage(path: &Path) -> bool {
+    std::path::absolute(path)
+        .and_then(|path| staging_dir(&path))
+        .and_then(fs::create_dir_all)
+        .is_ok()
+}
+
+/// Journal a move to the trash so `u` can put it back
+pub fn record_trash(path: &Path, trashed_to: &Path, size_bytes: u64, files: u64) -> io::Result<()> {
+    let path = std::path::absolute(path)?;
+    let transaction = Transaction {
+        id: new_id(),
+        timestamp: chrono::Utc::now().timestamp(),
+        kind: TransactionKind::Trash,
+        path: encode_path(&path),
+        moved_to: encode_path(trashed_to),
+        size_bytes,
+        files,
+    };
+    update_journal(|journal| journal.push(transaction))
+}
+
+/// Transactions restored by an undo, and newer ones that couldn't be
+pub struct Undo {
+    pub restored: Vec<Transaction>,
+    pub skipped: Vec<(Transaction, io::Error)>,
+}
+
+/// Restore the `count` most recent transactions that can be restored
+pub fn undo(count: usize) -> io::Result<Undo> {
+    let _lock = lock_journal()?;
+    let mut journal = load_journal()?;
+    let undo = undo_in(&mut journal, count);
+    save_journal(&journal)?;
+    Ok(undo)
+}
+
+/// Restore from the end of `journal`, skipping entries that fail so older
+/// ones stay reachable. Skipped entries are kept unless their data is gone.
+fn undo_in(journal: &mut Vec<Transaction>, count: usize) -> Undo {
+    let mut undo = Undo {
+        restored: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut index = journal.len();
+    while index > 0 && undo.restored.len() < count {
+        index -= 1;
+        match restore(&journal[index]) {
+            Ok(()) => undo.restored.push(journal.remove(index)),
+            Err(e) if journal[index].moved_path().symlink_metadata().is_err() => {
+                undo.skipped.push((journal.remove(index), e));
+            }
+            Err(e) => undo.skipped.push((journal[index].clone(), e)),
+        }
+    }
+    undo
+}
+
+/// Move a transaction's entry back where it was. Nothing is overwritten.
+fn restore(transaction: &Transaction) -> io::Result<()> {
+    let original = transaction.original_path();
+    let moved = transaction.moved_path();
+    if original.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists again, not overwriting it", original.display()),
+        ));
+    }
+    fs::rename(&moved, &original)?;
+
+    match transaction.kind {
+        TransactionKind::Staged => {
+            if let Some(dir) = moved.parent() {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+        TransactionKind::Trash => {
+            // files/<name> pairs with info/<name>.trashinfo
+            if let (Some(trash), Some(name)) =
+                (moved.parent().and_then(Path::parent), moved.file_name())
+            {
+                let mut info_name = name.to_os_string();
+                info_name.push(".trashinfo");
+                let _ = fs::remove_file(trash.join("info").join(info_name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete staged entries older than `grace_secs` (all for None), forget
+/// expired trash moves and delete staging directories no transaction refers to
+/// (left behind by a crash between staging and journaling)
+pub fn purge(grace_secs: Option<i64>) -> io::Result<Vec<(Transaction, DeleteResult)>> {
+    let expired = |t: &Transaction| grace_secs.is_none_or(|grace| t.age_secs() >= grace);
+
+    // Take the expired transactions off the journal first so they can't be
+    // restored while their files are being deleted
+    let mut purged = Vec::new();
+    let mut orphans = Vec::new();
+    update_journal(|journal| {
+        orphans = orphaned_staging_dirs(journal, &undo_dir().join("staged"));
+        let (old, kept): (Vec<_>, Vec<_>) = journal.drain(..).partition(|t| expired(t));
+        *journal = kept;
+        purged = old;
+    })?;
+
+    for dir in orphans {
+        let start_time = std::time::Instant::now();
+        let result = delete_staged(&dir);
+        log_purge(&dir, &result, start_time);
+    }
+
+    let mut results = Vec::new();
+    for transaction in purged {
+        if transaction.kind != TransactionKind::Staged {
+            continue;
+        }
+        let moved = transaction.moved_path();
+        let start_time = std::time::Instant::now();
+        let result = delete_staged(&moved);
+        if let Some(dir) = moved.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        log_purge(&transaction.original_path(), &result, start_time);
+        results.push((transaction, result));
+    }
+    Ok(results)
+}
+
+fn delete_staged(path: &PathBuf) -> DeleteResult {
+    delete::delete_directory(path, None, &AtomicBool::new(false)).unwrap_or_else(|e| DeleteResult {
+        total_bytes: 0,
+        total_files: 0,
+        errors: vec![e.to_string()],
+        moved_to: None,
+        cancelled: false,
+    })
+}
+
+fn log_purge(path: &Path, result: &DeleteResult, start_time: std::time::Instant) {
+    let log = logger::DeleteLog {
+        timestamp: Local::now().to_rfc3339(),
+        action: "purge".to_string(),
+        path: path.display().to_string(),
+        size_bytes: result.total_bytes,
+        dry_run: false,
+        status: if result.errors.is_empty() {
+            "success"
+        } else {
+            "error"
+        }
+        .to_string(),
+        files_deleted: result.total_files,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        errors: (!result.errors.is_empty()).then(|| result.errors.clone()),
+        moved_to: None,
+    };
+    let _ = logger::write_log(&log);
+}
+
+/// Subdirectories of `home_staging` and of the staging directories the
+/// journal refers to that hold no journaled transaction
+fn orphaned_staging_dirs(journal: &[Transaction], home_staging: &Path) -> Vec<PathBuf> {
+    let referenced: HashSet<PathBuf> = journal
+        .iter()
+        .filter(|t| t.kind == TransactionKind::Staged)
+        .filter_map(|t| t.moved_path().parent().map(Path::to_path_buf))
+        .collect();
+    let mut roots: HashSet<&Path> = referenced.iter().filter_map(|dir| dir.parent()).collect();
+    roots.insert(home_staging);
+
+    let mut orphans: Vec<PathBuf> = roots
+        .into_iter()
+        .filter_map(|root| fs::read_dir(root).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| !referenced.contains(path))
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+/// Exclusive lock on the journal across mcdu processes, held from loading it
+/// to saving it. A separate file is locked because saving replaces the journal.
+#[cfg(unix)]
+fn lock_journal() -> io::Result<nix::fcntl::Flock<fs::File>> {
+    let dir = undo_dir();
+    fs::create_dir_all(&dir)?;
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("journal.lock"))?;
+    nix::fcntl::Flock::lock(file, nix::fcntl::FlockArg::LockExclusive)
+        .map_err(|(_, errno)| errno.into())
+}
+
+#[cfg(not(unix))]
+fn lock_journal() -> io::Result<std::sync::MutexGuard<'static, ()>> {
+    static JOURNAL_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    Ok(JOURNAL_LOCK.lock().unwrap())
+}
+
+fn update_journal(change: impl FnOnce(&mut Vec<Transaction>)) -> io::Result<()> {
+    let _lock = lock_journal()?;
+    let mut journal = load_journal()?;
+    change(&mut journal);
+    save_journal(&journal)
+}
+
+fn load_journal() -> io::Result<Vec<Transaction>> {
+    match fs::read_to_string(journal_path()) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save_journal(journal: &[Transaction]) -> io::Result<()> {
+    let path = journal_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Replaced atomically, a crash mid-write must not lose undo records
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(journal)?)?;
+    fs::rename(tmp, path)
+}
+
+fn undo_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".mcdu").join("undo")
+}
+
+fn journal_path() -> PathBuf {
+    undo_dir().join("journal.json")
+}
+
+/// Whether `path` is a staging directory. Scans leave them out, their
+/// contents are already deleted as far as the user is concerned.
+pub fn is_staging_dir(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) if name.as_encoded_bytes().starts_with(b".mcdu-staged-") => true,
+        Some(name) if name == "staged" => path == undo_dir().join("staged"),
+        _ => false,
+    }
+}
+
+/// Staging directory on the filesystem of `path`, so staging is a rename
+#[cfg(unix)]
+fn staging_dir(path: &Path) -> io::Result<PathBuf> {
+    let home_staging = undo_dir().join("staged");
+    if crate::trash::same_device(path, &home_staging) {
+        return Ok(home_staging);
+    }
+    let top = crate::trash::mount_top(path)?;
+    Ok(top.join(format!(".mcdu-staged-{}", nix::unistd::getuid().as_raw())))
+}
+
+#[cfg(not(unix))]
+fn staging_dir(_path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::other(
+        "staged deletion is only supported on Unix",
+    ))
+}
+
+fn new_id() -> String {
+    format!(
+        "{}-{}-{}",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        std::process::id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(kind: TransactionKind, path: &Path, moved_to: &Path) -> Transaction {
+        Transaction {
+            id: "test".to_string(),
+            timestamp: 0,
+            kind,
+            path: encode_path(path),
+            moved_to: encode_path(moved_to),
+            size_bytes: 0,
+            files: 1,
+        }
+    }
+
+    #[test]
+    fn restore_puts_entries_back_without_overwriting() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("staged/1")).unwrap();
+        fs::create_dir_all(root.join("Trash/files")).unwrap();
+        fs::create_dir_all(root.join("Trash/info")).unwrap();
+        fs::write(root.join("staged/1/build.log"), b"log").unwrap();
+        fs::write(root.join("Trash/files/notes"), b"notes").unwrap();
+        fs::write(root.join("Trash/info/notes.trashinfo"), b"[Trash Info]\n").unwrap();
+
+        let staged = transaction(
+            TransactionKind::Staged,
+            &root.join("build.log"),
+            &root.join("staged/1/build.log"),
+        );
+        restore(&staged).unwrap();
+        assert_eq!(fs::read(root.join("build.log")).unwrap(), b"log");
+        assert!(!root.join("staged/1").exists());
+
+        let trashed = transaction(
+            TransactionKind::Trash,
+            &root.join("notes"),
+            &root.join("Trash/files/notes"),
+        );
+        fs::write(root.join("notes"), b"new").unwrap();
+        let err = restore(&trashed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(root.join("notes")).unwrap(), b"new");
+
+        fs::remove_file(root.join("notes")).unwrap();
+        restore(&trashed).unwrap();
+        assert_eq!(fs::read(root.join("notes")).unwrap(), b"notes");
+        assert!(!root.join("Trash/info/notes.trashinfo").exists());
+    }
+
+    #[test]
+    fn undo_skips_entries_that_cannot_be_restored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("staged/1")).unwrap();
+        fs::create_dir_all(root.join("staged/3")).unwrap();
+        fs::write(root.join("staged/1/old"), b"old").unwrap();
+        fs::write(root.join("staged/3/blocked"), b"staged").unwrap();
+        fs::write(root.join("blocked"), b"new").unwrap();
+
+        let mut journal = vec![
+            transaction(
+                TransactionKind::Staged,
+                &root.join("old"),
+                &root.join("staged/1/old"),
+            ),
+            // Purged or emptied from the trash by hand
+            transaction(
+                TransactionKind::Staged,
+                &root.join("gone"),
+                &root.join("staged/2/gone"),
+            ),
+            transaction(
+                TransactionKind::Staged,
+                &root.join("blocked"),
+                &root.join("staged/3/blocked"),
+            ),
+        ];
+
+        let undo = undo_in(&mut journal, 1);
+        assert_eq!(undo.restored.len(), 1);
+        assert_eq!(undo.restored[0].original_path(), root.join("old"));
+        assert_eq!(fs::read(root.join("old")).unwrap(), b"old");
+        assert_eq!(undo.skipped.len(), 2);
+        // The blocked entry stays to be retried or purged, the lost one is dropped
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].original_path(), root.join("blocked"));
+    }
+
+    #[test]
+    fn orphaned_staging_dirs_are_found_next_to_journaled_ones() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let home_staging = root.join("home/staged");
+        let mount_staging = root.join("mnt/.mcdu-staged-1000");
+        for dir in [
+            home_staging.join("crashed"),
+            mount_staging.join("1"),
+            mount_staging.join("2"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let journal = vec![transaction(
+            TransactionKind::Staged,
+            &root.join("mnt/old"),
+            &mount_staging.join("1/old"),
+        )];
+
+        assert_eq!(
+            orphaned_staging_dirs(&journal, &home_staging),
+            vec![home_staging.join("crashed"), mount_staging.join("2")]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// Live filesystem watching (inotify, Linux only)
+use crate::tree::Node;
+use std::path::{Path, PathBuf};
+
+/// Upper bound on watched directories, well below the default
+/// fs.inotify.max_user_watches so other programs keep working
+const MAX_WATCHES: usize = 4096;
+
+/// Collect the directories to watch: `root` and the scanned directories
+/// below it, breadth first so the levels closest to the view win
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn watch_targets(root: &Path, node: &Node) -> Vec<PathBuf> {
+    let mut targets = vec![root.to_path_buf()];
+    let mut queue = vec![(root.to_path_buf(), node)];
+    while !queue.is_empty() && targets.len() < MAX_WATCHES {
+        let mut next = Vec::new();
+        for (path, node) in queue {
+            for child in node.children.iter().filter(|c| c.is_dir) {
+                if child.other_fs || child.excluded || targets.len() >= MAX_WATCHES {
+                    continue;
+                }
+                let child_path = path.join(&child.name);
+                targets.push(child_path.clone());
+                next.push((child_path, child));
+            }
+        }
+        queue = next;
+    }
+    targets
+}
+
+/// Reduce changed directories to the outermost ones, since rescanning a
+/// directory also picks up changes in its invalidated descendants
+pub fn outermost(mut dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+    dirs.sort();
+    dirs.dedup();
+    let mut result: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if !result.iter().any(|kept| dir.starts_with(kept)) {
+            result.push(dir);
+        }
+    }
+    result
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::Watcher;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::watch_targets;
+    use crate::tree::Node;
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+    use std::collections::{HashMap, HashSet};
+    use std::path::{Path, PathBuf};
+
+    /// Watches the displayed directory and its scanned subdirectories
+    pub struct Watcher {
+        inotify: Inotify,
+        watches: HashMap<WatchDescriptor, PathBuf>,
+    }
+
+    impl Watcher {
+        pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+            let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+            Ok(Watcher {
+                inotify,
+                watches: HashMap::new(),
+            })
+        }
+
+        /// Watch `root` and the directories of its subtree, dropping watches
+        /// that are no longer part of it
+        pub fn watch_tree(&mut self, root: &Path, node: &Node) {
+            let targets = watch_targets(root, node);
+            let wanted: HashSet<&PathBuf> = targets.iter().collect();
+
+            let stale: Vec<WatchDescriptor> = self
+                .watches
+                .iter()
+                .filter(|(_, path)| !wanted.contains(path))
+                .map(|(wd, _)| *wd)
+                .collect();
+            for wd in stale {
+                let _ = self.inotify.rm_watch(wd);
+                self.watches.remove(&wd);
+            }
+
+            let watched: HashSet<PathBuf> = self.watches.values().cloned().collect();
+            let flags = AddWatchFlags::IN_MODIFY
+                | AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_MOVE
+                | AddWatchFlags::IN_ONLYDIR
+                | AddWatchFlags::IN_DONT_FOLLOW;
+            for path in targets {
+                if watched.contains(&path) {
+                    continue;
+                }
+                // Unreadable or vanished directories simply aren't watched
+                if let Ok(wd) = self.inotify.add_watch(&path, flags) {
+                    self.watches.insert(wd, path);
+                }
+            }
+        }
+
+        /// Directories whose contents changed since the last call, sorted
+        pub fn changed_dirs(&mut self) -> Vec<PathBuf> {
+            let mut changed = Vec::new();
+            // EAGAIN: nothing happened since the last read
+            while let Ok(events) = self.inotify.read_events() {
+                for event in events {
+                    if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                        // The kernel dropped the watch (directory removed)
+                        self.watches.remove(&event.wd);
+                    } else if let Some(dir) = self.watches.get(&event.wd) {
+                        changed.push(dir.clone());
+                    }
+                }
+            }
+            changed.sort();
+            changed.dedup();
+            changed
+        }
+
+        pub fn watch_count(&self) -> usize {
+            self.watches.len()
+        }
+    }
+}
+
+/// Stand-in for platforms without inotify
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Err("watch mode needs inotify and is only available on Linux".into())
+    }
+
+    pub fn watch_tree(&mut self, _root: &Path, _node: &Node) {}
+
+    pub fn changed_dirs(&mut self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    pub fn watch_count(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outermost_drops_nested_directories() {
+        let dirs = vec![
+            PathBuf::from("/data/a/b"),
+            PathBuf::from("/data/a"),
+            PathBuf::from("/data/ab"),
+            PathBuf::from("/data/a"),
+        ];
+        assert_eq!(
+            outermost(dirs),
+            vec![PathBuf::from("/data/a"), PathBuf::from("/data/ab")]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn watcher_reports_changed_subdirectories() {
+        use std::fs;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        let tree = Node::dir(
+            root.as_os_str().to_os_string(),
+            vec![Node::dir("logs".into(), Vec::new())],
+        );
+
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch_tree(&root, &tree);
+        assert_eq!(watcher.watch_count(), 2);
+        assert!(watcher.changed_dirs().is_empty());
+
+        fs::write(root.join("logs/app.log"), b"line\n").unwrap();
+        assert_eq!(watcher.changed_dirs(), vec![root.join("logs")]);
+    }
+}